@@ -0,0 +1,93 @@
+//!
+//! The grammar of the ids of logical units, like `FOO.1::BAR-BAZ.2`
+//!
+//! An id is a non-empty sequence of parts separated by `::`, each part being a
+//! tag and a version joined by a `.`. A tag starts with a letter or `_`, which
+//! is followed by at least one letter, digit, `_` or `-`, and a version is a
+//! positive integer.
+//!
+//! The grammar is kept in this crate so that `kontxt` and `kontxt-macros`
+//! agree on it, without the macros depending on all of `kontxt`.
+//!
+
+/// The parts of the logical unit id `id`, each a tag and its version, or
+/// `None` if `id` is not a valid id
+pub fn parse(id: &str) -> Option<Vec<(String, u32)>> {
+    id.split("::").map(part).collect()
+}
+
+/// Whether `id` is a valid logical unit id
+pub fn is_valid(id: &str) -> bool {
+    parse(id).is_some()
+}
+
+/// Whether the character `c` can occur in a logical unit id
+pub fn is_id_char(c: char) -> bool {
+    is_tag_char(c) || c == '.' || c == ':'
+}
+
+fn part(part: &str) -> Option<(String, u32)> {
+    let (tag, version) = part.split_once('.')?;
+    if !is_tag(tag) {
+        return None;
+    }
+    Some((tag.to_string(), parse_version(version)?))
+}
+
+fn is_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    chars.next().is_some_and(is_init_char) && tag.len() > 1 && chars.all(is_tag_char)
+}
+
+fn is_init_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_tag_char(c: char) -> bool {
+    is_init_char(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn parse_version(version: &str) -> Option<u32> {
+    let is_version = !version.starts_with('0') && version.chars().all(|c| c.is_ascii_digit());
+    version.parse().ok().filter(|_| is_version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_ids() {
+        assert_eq!(
+            parse("FOO.1::BAR-BAZ.12"),
+            Some(vec![("FOO".to_string(), 1), ("BAR-BAZ".to_string(), 12)])
+        );
+        for id in ["_F0.3", "foo_bar.1", "A-.2"].iter() {
+            assert!(is_valid(id), "{} is valid", id)
+        }
+    }
+
+    #[test]
+    fn can_reject_invalid_ids() {
+        for id in [
+            "",
+            "F.1",
+            "FOO",
+            "FOO.",
+            "FOO.0",
+            "FOO.01",
+            "FOO.+1",
+            "FOO.1.2",
+            "FOO.1::",
+            "::FOO.1",
+            "1FOO.1",
+            "-FOO.1",
+            "FOO BAR.1",
+            "FOO.99999999999",
+        ]
+        .iter()
+        {
+            assert!(!is_valid(id), "{} is invalid", id)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//!
+//! Attributes for tagging rust items with the logical units they implement
+//!
+//! ```ignore
+//! use kontxt_macros::implements;
+//!
+//! #[implements("SPEC-HELLO.1")]
+//! fn hello() {
+//!     println!("Hello world!")
+//! }
+//! ```
+//!
+//! The tags are validated at compile time, and the annotated items are found
+//! by `kontxt` when it loads the logical units of a repo.
+//!
+
+use {
+    proc_macro::TokenStream,
+    syn::{parse::Parser, punctuated::Punctuated, LitStr, Token},
+};
+
+// The error for a `tag` that is not a valid logical unit id, if it is not
+fn tag_error(tag: &LitStr) -> Option<syn::Error> {
+    (!kontxt_id::is_valid(&tag.value())).then(|| {
+        syn::Error::new(
+            tag.span(),
+            format!("invalid logical unit tag `{}`", tag.value()),
+        )
+    })
+}
+
+/// `#[implements("TAG", ...)]` records that the annotated item implements the
+/// logical units with the given tags.
+///
+/// The item is left unchanged. Compilation fails if any of the tags is not a
+/// valid logical unit id.
+#[proc_macro_attribute]
+pub fn implements(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let tags = match Punctuated::<LitStr, Token![,]>::parse_terminated.parse(attr) {
+        Ok(tags) => tags,
+        Err(err) => return with_error(err, item),
+    };
+    if tags.is_empty() {
+        let err = syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "expected the tags of the implemented logical units, e.g. `#[implements(\"FOO.1\")]`",
+        );
+        return with_error(err, item);
+    }
+    match tags.iter().filter_map(tag_error).reduce(|mut errs, err| {
+        errs.combine(err);
+        errs
+    }) {
+        Some(err) => with_error(err, item),
+        None => item,
+    }
+}
+
+// Emit the `err` along with the unchanged `item`, so that the item is still
+// available to the rest of the compilation
+fn with_error(err: syn::Error, item: TokenStream) -> TokenStream {
+    let mut out = TokenStream::from(err.to_compile_error());
+    out.extend(item);
+    out
+}
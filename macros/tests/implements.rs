@@ -0,0 +1,19 @@
+use kontxt_macros::implements;
+
+#[implements("FOO.1::IMPL.1")]
+fn tagged() -> u8 {
+    1
+}
+
+#[implements("FOO.1::BAR.1", "FOO.1::BAZ.1")]
+struct Tagged(u8);
+
+#[test]
+fn tagged_items_are_unchanged() {
+    assert_eq!(tagged(), Tagged(1).0)
+}
+
+#[test]
+fn invalid_tags_fail_to_compile() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use kontxt_macros::implements;
+
+#[implements("FOO.1::", "bar")]
+fn tagged() {}
+
+#[implements()]
+struct Untagged;
+
+fn main() {}
@@ -1,9 +1,12 @@
 use {
     crate::{
-        logical_unit::{Kind, LogicalUnit},
-        pandoc,
+        language::{self, Language, SourceLine},
+        logical_unit::{self, Kind, LogicalUnit},
+        markdown::{self, Definition, UnitRef},
+        model,
         parser::{parser, TAG_ID_RE},
         repo::Repo,
+        rust,
     },
     anyhow::{Context, Result},
     log,
@@ -11,7 +14,7 @@ use {
         collections::HashSet,
         convert::{TryFrom, TryInto},
         fmt,
-        fs::File,
+        fs::{self, File},
         io,
         io::BufRead, // modularity is awkward in rust
         path::{Path, PathBuf},
@@ -31,12 +34,19 @@ pub enum Error {
 
 pub enum SourceFileKind {
     Markdown,
-    Rust,
+    Tla,
+    Quint,
+    Alloy,
+    Code(&'static Language),
 }
 
 struct SourceFile<'a> {
     kind: SourceFileKind,
+    // The directory the `path` is relative to
+    root: &'a Path,
     path: &'a Path,
+    // The contents of the file, if not read from disk
+    contents: Option<&'a str>,
     repo: Option<&'a Repo>,
 }
 
@@ -44,26 +54,74 @@ impl<'a> SourceFile<'a> {
     fn new(kind: SourceFileKind, path: &'a Path) -> Self {
         Self {
             kind,
+            root: Path::new(""),
             path,
+            contents: None,
             repo: None,
         }
     }
 
+    fn set_root(&mut self, root: &'a Path) {
+        self.root = root
+    }
+
     fn set_repo(&mut self, repo: Option<&'a Repo>) {
         self.repo = repo
     }
 
+    fn set_contents(&mut self, contents: &'a str) {
+        self.contents = Some(contents)
+    }
+
+    // The location of the file on disk
+    fn file(&self) -> PathBuf {
+        self.root.join(self.path)
+    }
+
+    fn read(&self) -> Result<String> {
+        match self.contents {
+            Some(contents) => Ok(contents.to_string()),
+            None => Ok(fs::read_to_string(self.file())?),
+        }
+    }
+
     fn units(&self) -> Result<HashSet<LogicalUnit>> {
         match self.kind {
             SourceFileKind::Markdown => self.units_of_md(),
-            SourceFileKind::Rust => self.units_of_src(),
+            SourceFileKind::Code(lang) => self.units_of_src(lang),
+            SourceFileKind::Tla => self.units_of_model(model::Language::Tla),
+            SourceFileKind::Quint => self.units_of_model(model::Language::Quint),
+            SourceFileKind::Alloy => self.units_of_model(model::Language::Alloy),
+        }
+    }
+
+    // The kind of the units in the file, as set by the repo's overrides, or
+    // else inferred from the file. `in_test` tells whether the unit is defined
+    // within test code.
+    fn unit_kind(&self, in_test: bool) -> Kind {
+        if let Some(kind) = self.repo.and_then(|r| r.kind_override(self.path)) {
+            return kind;
+        }
+        match self.kind {
+            SourceFileKind::Tla | SourceFileKind::Quint | SourceFileKind::Alloy => Kind::Model,
+            _ if is_model_file(self.path) => Kind::Model,
+            SourceFileKind::Markdown => Kind::Requirement,
+            SourceFileKind::Code(lang)
+                if in_test || is_in_tests_dir(self.path) || lang.is_test_file(self.path) =>
+            {
+                Kind::Verification
+            }
+            SourceFileKind::Code(_) => Kind::Implementation,
         }
     }
 
     fn units_of_md(&self) -> Result<HashSet<LogicalUnit>> {
-        pandoc::definitions_from_file(self.path)
+        let kind = self.unit_kind(false);
+        let source = self.read()?;
+        markdown::definitions_from_string(&source)
             .map(|defs| {
-                logical_units_of_defs(self.repo.cloned(), Some(self.path), &defs)
+                let refs = markdown::unit_refs(&source);
+                logical_units_of_defs(self.repo.cloned(), Some(self.path), kind, &defs, &refs)
                     .iter()
                     .cloned()
                     .collect()
@@ -79,42 +137,167 @@ impl<'a> SourceFile<'a> {
             })
     }
 
-    fn units_of_src(&self) -> Result<HashSet<LogicalUnit>> {
-        let mut file = File::open(self.path)?;
-        self.units_of_src_reader(&mut file)
+    fn units_of_model(&self, lang: model::Language) -> Result<HashSet<LogicalUnit>> {
+        let src = self.read()?;
+        let kind = self.unit_kind(false);
+        let units = model::tagged_comments(lang, &src)
+            .iter()
+            .filter_map(|comment| {
+                LogicalUnit::new(
+                    self.repo.cloned(),
+                    Some(self.path),
+                    Some(comment.line),
+                    kind.clone(),
+                    &comment.tag,
+                    &comment.content(),
+                )
+                .map(|unit| LogicalUnit {
+                    references: comment.references.clone(),
+                    ..unit
+                })
+                .map_err(|_| log::error!("unable to parse unit ID {}", comment.tag))
+                .ok()
+            })
+            .collect();
+        Ok(units)
+    }
+
+    fn units_of_src(&self, lang: &Language) -> Result<HashSet<LogicalUnit>> {
+        match self.contents {
+            Some(contents) => self.units_of_src_reader(lang, &mut io::Cursor::new(contents)),
+            None => self.units_of_src_reader(lang, &mut File::open(self.file())?),
+        }
     }
 
-    fn units_of_src_reader(&self, reader: &mut impl io::Read) -> Result<HashSet<LogicalUnit>> {
-        let units: HashSet<LogicalUnit> = io::BufReader::new(reader)
+    fn units_of_src_reader(
+        &self,
+        lang: &Language,
+        reader: &mut impl io::Read,
+    ) -> Result<HashSet<LogicalUnit>> {
+        let lines = io::BufReader::new(reader)
             .lines()
-            .collect::<io::Result<Vec<String>>>()? // Fail if we errored on any read
-            .iter() // TODO avoid an intermediate collection?
+            .collect::<io::Result<Vec<String>>>()?; // Fail if we errored on any read
+        if std::ptr::eq(lang, &language::RUST) {
+            match rust::tags(self.path, &lines) {
+                Ok(tags) => return Ok(self.units_of_rust_tags(&lines, tags)),
+                Err(e) => log::warn!(
+                    "falling back to scanning the lines of {}, which cannot be parsed: {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        let source = lang.split_comments(&lines);
+        let in_test = (lang.test_lines)(&lines);
+        let units: HashSet<LogicalUnit> = source
+            .iter()
             .enumerate() // Get the line numbers
-            .flat_map(|line| self.unit_of_src_line(line))
+            .flat_map(|(n, line)| {
+                let comment_tags = line
+                    .comment
+                    .iter()
+                    .flat_map(|c| TAG_ID_RE.captures_iter(c))
+                    .filter_map(|c| c.name("tag"))
+                    .map(|tag| tag.as_str().to_string());
+                let attribute_tags = (lang.tagged_attributes)(&line.code);
+                comment_tags
+                    .chain(attribute_tags)
+                    .filter_map(|tag| self.unit_of_src_tag(lang, &source, in_test[n], n, &tag))
+                    .collect::<Vec<LogicalUnit>>()
+            })
             .collect();
         Ok(units)
     }
 
-    fn unit_of_src_line(&self, (n, line): (usize, &String)) -> Option<LogicalUnit> {
-        let id = TAG_ID_RE
-            .captures(line)
-            .and_then(|c| c.name("tag"))?
-            .as_str();
-        let content = "";
-        if let Ok(unit) = LogicalUnit::new(
+    fn units_of_rust_tags(&self, lines: &[String], tags: Vec<rust::Tag>) -> HashSet<LogicalUnit> {
+        let in_test = (language::RUST.test_lines)(lines);
+        tags.into_iter()
+            .filter_map(|tag| {
+                let n = tag.line as usize - 1;
+                let kind = self.unit_kind(tag.item.as_ref().map_or(in_test[n], |i| i.in_test));
+                let content = tag.item.as_ref().map_or("".into(), |i| i.description());
+                match LogicalUnit::new(
+                    self.repo.cloned(),
+                    Some(self.path),
+                    Some(tag.line),
+                    kind,
+                    &tag.tag,
+                    &content,
+                ) {
+                    Ok(unit) => Some(LogicalUnit {
+                        span: tag.item.map(|i| i.span),
+                        references: tag.references,
+                        ..unit
+                    }),
+                    Err(_) => {
+                        log::error!("unable to parse unit ID {}", tag.tag);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    // The unit tagged with `id` on line `n` (counting from 0)
+    fn unit_of_src_tag(
+        &self,
+        lang: &Language,
+        source: &[SourceLine],
+        in_test: bool,
+        n: usize,
+        id: &str,
+    ) -> Option<LogicalUnit> {
+        let content = item_at(lang, source, n).unwrap_or_default();
+        match LogicalUnit::new(
             self.repo.cloned(),
             Some(self.path),
-            Some(n.try_into().unwrap()),
-            Kind::Implementation,
+            Some((n + 1).try_into().unwrap()),
+            self.unit_kind(in_test),
             id,
-            content,
+            &content,
         ) {
-            Some(unit)
-        } else {
-            log::error!("unable to parse unit ID {}", id);
-            None
+            Ok(unit) => {
+                // The references of a unit in source code are those found in
+                // the comment block in which its tag appears.
+                let references =
+                    logical_unit::references_of_source(language::comment_block(source, n));
+                Some(LogicalUnit { references, ..unit })
+            }
+            Err(_) => {
+                log::error!("unable to parse unit ID {}", id);
+                None
+            }
+        }
+    }
+}
+
+/// The extensions of files in the languages used for writing models
+const MODEL_EXTENSIONS: [&str; 3] = ["tla", "qnt", "als"];
+
+fn is_model_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MODEL_EXTENSIONS.contains(&e))
+}
+
+fn is_in_tests_dir(path: &Path) -> bool {
+    path.parent()
+        .is_some_and(|dir| dir.components().any(|c| c.as_os_str() == "tests"))
+}
+
+// The description of the item tagged on line `n`: the first item defined from
+// line `n` on, skipping over the comments and attributes preceding it
+fn item_at(lang: &Language, source: &[SourceLine], n: usize) -> Option<String> {
+    for line in &source[n..] {
+        if let Some(item) = (lang.item_of_line)(&line.code) {
+            return Some(item);
+        }
+        let code = line.code.trim();
+        if !(code.is_empty() || code.starts_with("#[")) {
+            return None;
         }
     }
+    None
 }
 
 impl TryFrom<&Path> for SourceFileKind {
@@ -124,8 +307,12 @@ impl TryFrom<&Path> for SourceFileKind {
         if let Some(ext) = p.extension().and_then(|e| e.to_str()) {
             match ext {
                 "md" => Ok(SourceFileKind::Markdown),
-                "rs" => Ok(SourceFileKind::Rust),
-                _ => Err(Error::SourceFileExt(ext.to_string())),
+                "tla" => Ok(SourceFileKind::Tla),
+                "qnt" => Ok(SourceFileKind::Quint),
+                "als" => Ok(SourceFileKind::Alloy),
+                _ => Language::of_extension(ext)
+                    .map(SourceFileKind::Code)
+                    .ok_or_else(|| Error::SourceFileExt(ext.to_string())),
             }
         } else {
             Err(Error::SourceFileNoExt(p.to_path_buf()))
@@ -149,6 +336,12 @@ impl<'a> TryFrom<&'a Path> for SourceFile<'a> {
     }
 }
 
+// The kind of the file at `path`, which is parsed as configured for the `repo`
+fn kind_of_file(repo: Option<&Repo>, path: &Path) -> Result<SourceFileKind> {
+    let parsed_as = repo.map_or_else(|| path.to_path_buf(), |r| r.parsed_as(path));
+    Ok(SourceFileKind::try_from(parsed_as.as_path())?)
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Artifact {
     pub path: Option<PathBuf>,
@@ -163,9 +356,21 @@ impl Artifact {
         }
     }
 
-    /// Parse the file `path` into an artifact
-    pub fn from_file(repo: Option<&Repo>, path: &Path) -> Result<Artifact> {
-        let mut source_file = SourceFile::try_from(path)?;
+    /// Parse the file at `path`, relative to the `root` directory, into an
+    /// artifact found in the `repo`
+    pub fn from_file(root: &Path, repo: Option<&Repo>, path: &Path) -> Result<Artifact> {
+        let mut source_file = SourceFile::new(kind_of_file(repo, path)?, path);
+        source_file.set_root(root);
+        source_file.set_repo(repo);
+        let units = source_file.units()?;
+        Ok(Artifact::new(Some(path.to_owned()), units))
+    }
+
+    /// Parse the `contents` of the file at `path` into an artifact found in
+    /// the `repo`, without reading the file from disk
+    pub fn from_contents(repo: Option<&Repo>, path: &Path, contents: &str) -> Result<Artifact> {
+        let mut source_file = SourceFile::new(kind_of_file(repo, path)?, path);
+        source_file.set_contents(contents);
         source_file.set_repo(repo);
         let units = source_file.units()?;
         Ok(Artifact::new(Some(path.to_owned()), units))
@@ -173,8 +378,11 @@ impl Artifact {
 
     /// Parse the string `s` into an artifact with no source
     pub fn from_string(s: &str) -> Result<Artifact> {
-        pandoc::definitions_from_string(s)
-            .map(|defs| logical_units_of_defs(None, None, &defs))
+        markdown::definitions_from_string(s)
+            .map(|defs| {
+                let refs = markdown::unit_refs(s);
+                logical_units_of_defs(None, None, Kind::Requirement, &defs, &refs)
+            })
             .map(|lus| Artifact::new(None, lus.iter().cloned().collect()))
             .with_context(|| format!("parsing artifact from string {}", s))
     }
@@ -190,36 +398,75 @@ impl fmt::Display for Artifact {
     }
 }
 
-// Given the `pandoc_ast` representation of a description list,
-// this finds any items that are valid logical units.
+// Given the terms and definitions of a description list, and the `refs`
+// made in its source, this finds any items that are valid logical units.
 fn logical_units_of_defs(
     repo: Option<Repo>,
     file: Option<&Path>,
-    defs: &[(String, String)],
+    kind: Kind,
+    defs: &[Definition],
+    refs: &[UnitRef],
 ) -> Vec<LogicalUnit> {
-    // TODO Infer from file type?
     defs.iter()
-        .filter_map(|(tags, content)| {
-            parser::logical_unit_definiendum(tags).ok().and_then(|id| {
-                // TODO Determine kind from file type
-                let kind = Kind::Requirement;
-                // TODO Determine line
-                match LogicalUnit::new(repo.clone(), file, None, kind, &id, &content) {
-                    Ok(lu) => Some(lu),
-                    Err(err) => {
-                        // TODO Replace with logging
-                        println!("Error: {:?}", err);
-                        None
+        .filter_map(|def| {
+            parser::logical_unit_definiendum(&def.term)
+                .ok()
+                .and_then(|id| {
+                    match LogicalUnit::new(
+                        repo.clone(),
+                        file,
+                        def.line,
+                        kind.clone(),
+                        &id,
+                        &def.content,
+                    ) {
+                        Ok(lu) => Some(locate_references(lu, defs, refs)),
+                        Err(err) => {
+                            log::error!("unable to parse unit ID {}: {}", id, err);
+                            None
+                        }
                     }
-                }
-            })
+                })
         })
         .collect()
 }
 
+// The `unit` defined by one of the `defs`, with each of its references located
+// at the first of the `refs` to the same tag made in the source of its
+// definition: from the line of its term, up to the line of the next term.
+// References that are not made in the source, like those in code, are
+// dropped. The references are left as they are if the lines of terms are
+// unknown.
+fn locate_references(unit: LogicalUnit, defs: &[Definition], refs: &[UnitRef]) -> LogicalUnit {
+    let start = match unit.line {
+        Some(line) => line,
+        None => return unit,
+    };
+    let end = defs
+        .iter()
+        .filter_map(|d| d.line)
+        .filter(|line| *line > start)
+        .min();
+    let in_definition = |r: &&UnitRef| r.line >= start && end.is_none_or(|end| r.line < end);
+    let references = unit
+        .references
+        .iter()
+        .filter_map(|reference| {
+            let id = reference.id.to_string();
+            let found = refs.iter().filter(in_definition).find(|r| r.tag == id)?;
+            Some(logical_unit::Reference {
+                line: Some(found.line),
+                ..reference.clone()
+            })
+        })
+        .collect();
+    LogicalUnit { references, ..unit }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::language;
 
     #[test]
     fn can_parse_logical_unit() {
@@ -238,7 +485,7 @@ mod test {
             LogicalUnit::new(
                 None,
                 None,
-                None,
+                Some(2),
                 Kind::Requirement,
                 "FOO.1::BAR.1",
                 "Biz baz blam.",
@@ -247,7 +494,7 @@ mod test {
             LogicalUnit::new(
                 None,
                 None,
-                None,
+                Some(5),
                 Kind::Requirement,
                 "FOO.1::BAZ.1",
                 "Pop crink splot.",
@@ -256,7 +503,7 @@ mod test {
             LogicalUnit::new(
                 None,
                 None,
-                None,
+                Some(8),
                 Kind::Requirement,
                 "FOO.1::BOP.1",
                 "Can parse URLs",
@@ -281,7 +528,7 @@ mod test {
         let logical_units: HashSet<LogicalUnit> = vec![LogicalUnit::new(
             None,
             None,
-            None,
+            Some(2),
             Kind::Requirement,
             "TAG.1::IN-ANCHOR-TAG.1".into(),
             "We can parse tags in an anchor html element.".into(),
@@ -307,7 +554,7 @@ mod test {
         let logical_units: HashSet<LogicalUnit> = vec![LogicalUnit::new(
             None,
             None,
-            None,
+            Some(2),
             Kind::Requirement,
             "TAG.1::IN-ANCHOR-TAG.1".into(),
             "We can parse tags in an anchor html element.".into(),
@@ -322,37 +569,87 @@ mod test {
         assert_eq!(actual.unwrap(), expected)
     }
 
+    #[test]
+    fn can_locate_references_of_md_units() {
+        let spec = r#"
+|FOO.1|
+:  Foo, see [BAR.1],
+   and [BAZ.1::QUX.1].
+
+|BAR.1|
+:  Bar, see [FOO.1], not `[BAZ.1]`
+
+   ```
+   [BAZ.1]
+   ```
+"#;
+        type Located = (String, Option<u64>);
+        let mut actual: Vec<(String, Vec<Located>)> = Artifact::from_string(spec)
+            .unwrap()
+            .logical_units
+            .iter()
+            .map(|u| {
+                let refs = u
+                    .references
+                    .iter()
+                    .map(|r| (r.id.to_string(), r.line))
+                    .collect();
+                (u.id.to_string(), refs)
+            })
+            .collect();
+        actual.sort();
+        let expected = vec![
+            ("BAR.1".to_string(), vec![("FOO.1".to_string(), Some(7))]),
+            (
+                "FOO.1".to_string(),
+                vec![
+                    ("BAR.1".to_string(), Some(3)),
+                    ("BAZ.1::QUX.1".to_string(), Some(4)),
+                ],
+            ),
+        ];
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn can_prase_logical_units_from_rs_file() {
         let src = r#"
-// 1
 // 2
 // 3
-///4 |FOO.1::BAR.2::BAZ.1|
+// 4
+///5 |FOO.1::BAR.2::BAZ.1|
 fn some_fun() {}
-// 6
+// 7
 
-///8 |FLO.1::BOA.2::BOZ.1|
+///9 |FLO.1::BOA.2::BOZ.1|
 fn some_other_fun() {}
 "#;
         let expected: HashSet<LogicalUnit> = vec![
             LogicalUnit::new(
                 None,
                 Some(Path::new("/")),
-                Some(4),
+                Some(5),
                 Kind::Implementation,
                 "FOO.1::BAR.2::BAZ.1",
-                "",
+                "fn some_fun",
             )
+            .map(|u| LogicalUnit {
+                span: Some((5, 6)),
+                ..u
+            })
             .unwrap(),
             LogicalUnit::new(
                 None,
                 Some(Path::new("/")),
-                Some(8),
+                Some(9),
                 Kind::Implementation,
                 "FLO.1::BOA.2::BOZ.1",
-                "",
+                "fn some_other_fun",
             )
+            .map(|u| LogicalUnit {
+                span: Some((9, 10)),
+                ..u
+            })
             .unwrap(),
         ]
         .iter()
@@ -360,10 +657,250 @@ fn some_other_fun() {}
         .collect();
 
         let mut reader = io::Cursor::new(src);
-        let actual = SourceFile::from(SourceFileKind::Rust)
-            .units_of_src_reader(&mut reader)
+        let actual = SourceFile::from(SourceFileKind::Code(&language::RUST))
+            .units_of_src_reader(&language::RUST, &mut reader)
             .unwrap();
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn can_parse_references_from_rs_doc_comments() {
+        let src = r#"
+/// Implements [FOO.1::BAR.1]
+/// |FOO.1::BAR.1::IMPL.1|
+/// and also [FOO.1]
+fn some_fun() {}
+
+/// [FLO.1] is not in the block of the tag
+fn some_other_fun() {}
+
+fn yet_another_fun() {
+    // |FOO.1::BAR.1::IMPL.2|
+    // See [FOO.1::BAR.1]
+}
+"#;
+        let mut reader = io::Cursor::new(src);
+        let mut actual: Vec<Vec<(String, Option<u64>)>> =
+            SourceFile::from(SourceFileKind::Code(&language::RUST))
+                .units_of_src_reader(&language::RUST, &mut reader)
+                .unwrap()
+                .iter()
+                .map(|u| {
+                    u.references
+                        .iter()
+                        .map(|r| (r.id.to_string(), r.line))
+                        .collect()
+                })
+                .collect();
+        actual.sort();
+
+        assert_eq!(
+            actual,
+            vec![
+                vec![
+                    ("FOO.1::BAR.1".to_string(), Some(2)),
+                    ("FOO.1".to_string(), Some(4))
+                ],
+                vec![("FOO.1::BAR.1".to_string(), Some(12))],
+            ]
+        );
+    }
+
+    #[test]
+    fn can_infer_kinds_of_rs_units() {
+        let src = r#"
+/// |FOO.1::IMPL.1|
+fn foo() {}
+
+/// |FOO.1::TEST.1|
+#[test]
+fn test_foo() {
+    assert!(true)
+}
+
+#[cfg(test)]
+mod test {
+    // |FOO.1::TEST.2|
+    fn helper() {}
+}
+
+/// |FOO.1::IMPL.2|
+fn bar() {}
+"#;
+        let mut reader = io::Cursor::new(src);
+        let mut actual: Vec<(String, Kind)> =
+            SourceFile::from(SourceFileKind::Code(&language::RUST))
+                .units_of_src_reader(&language::RUST, &mut reader)
+                .unwrap()
+                .iter()
+                .map(|u| (u.id.to_string(), u.kind.clone()))
+                .collect();
+        actual.sort();
+
+        let expected = vec![
+            ("FOO.1::IMPL.1".to_string(), Kind::Implementation),
+            ("FOO.1::IMPL.2".to_string(), Kind::Implementation),
+            ("FOO.1::TEST.1".to_string(), Kind::Verification),
+            ("FOO.1::TEST.2".to_string(), Kind::Verification),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_infer_kinds_from_paths() {
+        let kind_of = |path: &str| {
+            let path = Path::new(path);
+            SourceFile::try_from(path).unwrap().unit_kind(false)
+        };
+        assert_eq!(kind_of("src/lib.rs"), Kind::Implementation);
+        assert_eq!(kind_of("tests/cli.rs"), Kind::Verification);
+        assert_eq!(kind_of("tests/spec.md"), Kind::Requirement);
+        assert!(is_model_file(Path::new("spec/Consensus.tla")));
+    }
+
+    #[test]
+    fn units_of_alloy_files_are_models() {
+        let path = Path::new("specs/filesystem.als");
+        let src = "// |FS.1::DIR.1| A directory\nsig Dir {}\n";
+        let actual: Vec<(String, Kind, String)> = Artifact::from_contents(None, path, src)
+            .unwrap()
+            .logical_units
+            .iter()
+            .map(|u| (u.id.to_string(), u.kind.clone(), u.content.clone()))
+            .collect();
+        assert_eq!(
+            actual,
+            vec![(
+                "FS.1::DIR.1".to_string(),
+                Kind::Model,
+                "Dir: A directory".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn only_finds_tags_in_comments() {
+        let src = r#"package foo
+
+// Implements |FOO.1::IMPL.1| and |FOO.1::IMPL.2|
+func Foo() string {
+	return "|FOO.1::NOT.1|"
+}
+"#;
+        let mut reader = io::Cursor::new(src);
+        let mut actual: Vec<(String, Option<u64>)> =
+            SourceFile::from(SourceFileKind::Code(&language::GO))
+                .units_of_src_reader(&language::GO, &mut reader)
+                .unwrap()
+                .iter()
+                .map(|u| (u.id.to_string(), u.line))
+                .collect();
+        actual.sort();
+
+        let expected = vec![
+            ("FOO.1::IMPL.1".to_string(), Some(3)),
+            ("FOO.1::IMPL.2".to_string(), Some(3)),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn can_parse_tags_from_rs_attributes() {
+        let src = r##"
+#[derive(Debug)]
+#[kontxt_macros::implements("FOO.1::IMPL.1", "FOO.1::IMPL.2")]
+pub struct Foo(u8);
+
+/// |FOO.1::IMPL.3|
+#[implements("FOO.1::IMPL.4")]
+impl fmt::Display for Foo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // |FOO.1::IMPL.5|
+        write!(f, "{}", "#[implements(\"FOO.1::NOT.2\")]")
+    }
+}
+"##;
+        let mut reader = io::Cursor::new(src);
+        let mut actual: Vec<(String, Option<u64>, String)> =
+            SourceFile::from(SourceFileKind::Code(&language::RUST))
+                .units_of_src_reader(&language::RUST, &mut reader)
+                .unwrap()
+                .iter()
+                .map(|u| (u.id.to_string(), u.line, u.content.clone()))
+                .collect();
+        actual.sort();
+
+        let expected = vec![
+            (
+                "FOO.1::IMPL.1".to_string(),
+                Some(3),
+                "struct Foo".to_string(),
+            ),
+            (
+                "FOO.1::IMPL.2".to_string(),
+                Some(3),
+                "struct Foo".to_string(),
+            ),
+            (
+                "FOO.1::IMPL.3".to_string(),
+                Some(6),
+                "impl fmt::Display for Foo".to_string(),
+            ),
+            (
+                "FOO.1::IMPL.4".to_string(),
+                Some(7),
+                "impl fmt::Display for Foo".to_string(),
+            ),
+            (
+                "FOO.1::IMPL.5".to_string(),
+                Some(10),
+                "fn Foo::fmt".to_string(),
+            ),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn falls_back_to_lines_for_unparsable_rs_files() {
+        let src = r#"
+/// |FOO.1::IMPL.1|
+fn foo( {}
+"#;
+        let mut reader = io::Cursor::new(src);
+        let actual: Vec<(String, Option<u64>, String)> =
+            SourceFile::from(SourceFileKind::Code(&language::RUST))
+                .units_of_src_reader(&language::RUST, &mut reader)
+                .unwrap()
+                .iter()
+                .map(|u| (u.id.to_string(), u.line, u.content.clone()))
+                .collect();
+        assert_eq!(
+            actual,
+            vec![("FOO.1::IMPL.1".to_string(), Some(2), "fn foo".to_string())]
+        );
+    }
+
+    #[test]
+    fn can_parse_files_relative_to_a_root() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("src")).unwrap();
+        fs::write(
+            root.path().join("src/lib.rs"),
+            "/// |FOO.1::IMPL.1|\nfn foo() {}\n",
+        )
+        .unwrap();
+
+        let path = Path::new("src/lib.rs");
+        let artifact = Artifact::from_file(root.path(), None, path).unwrap();
+        let actual: Vec<(String, Option<PathBuf>)> = artifact
+            .logical_units
+            .iter()
+            .map(|u| (u.id.to_string(), u.file.clone()))
+            .collect();
+        assert_eq!(
+            actual,
+            vec![("FOO.1::IMPL.1".to_string(), Some(path.to_path_buf()))]
+        );
+    }
 }
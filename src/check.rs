@@ -0,0 +1,508 @@
+//! Consistency checks over the logical units in a context
+
+use {
+    crate::{
+        logical_unit::{self, Id, Kind, LogicalUnit, Reference},
+        repo::Repo,
+    },
+    serde::Serialize,
+    std::{
+        collections::{HashMap, HashSet},
+        fmt,
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unknown check {0}")]
+    UnknownCheck(String),
+    #[error("unknown severity {0}")]
+    UnknownSeverity(String),
+    #[error("invalid check level {0}, expected CHECK=SEVERITY")]
+    InvalidLevel(String),
+}
+
+/// The consistency checks that can be run over a set of logical units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// Two units define the same tag
+    DuplicateUnit,
+    /// A unit's parent is missing
+    OrphanUnit,
+    /// A unit refers to a unit that is missing
+    DanglingReference,
+    /// A requirement has no implementation
+    UnimplementedRequirement,
+    /// An implementation's spec is missing
+    UnknownSpec,
+    /// A unit is defined outside of the repo that owns its prefix
+    ForeignDefinition,
+}
+
+impl Check {
+    pub const ALL: [Check; 6] = [
+        Check::DuplicateUnit,
+        Check::OrphanUnit,
+        Check::DanglingReference,
+        Check::UnimplementedRequirement,
+        Check::UnknownSpec,
+        Check::ForeignDefinition,
+    ];
+
+    /// The severity of problems found by the check, unless configured otherwise
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Check::DuplicateUnit => Severity::Error,
+            Check::OrphanUnit => Severity::Warning,
+            Check::DanglingReference => Severity::Error,
+            Check::UnimplementedRequirement => Severity::Warning,
+            Check::UnknownSpec => Severity::Error,
+            Check::ForeignDefinition => Severity::Warning,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Check::DuplicateUnit => "duplicate-unit",
+            Check::OrphanUnit => "orphan-unit",
+            Check::DanglingReference => "dangling-reference",
+            Check::UnimplementedRequirement => "unimplemented-requirement",
+            Check::UnknownSpec => "unknown-spec",
+            Check::ForeignDefinition => "foreign-definition",
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Check {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Check::ALL
+            .iter()
+            .find(|c| c.name() == s)
+            .copied()
+            .ok_or_else(|| Error::UnknownCheck(s.to_string()))
+    }
+}
+
+/// The severity of a problem found by a check
+///
+/// Problems with severity `Allow` are not reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Allow,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Severity::Allow => "allow",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Severity::Allow),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(Error::UnknownSeverity(s.to_string())),
+        }
+    }
+}
+
+/// A configured severity for a check, parsed from `CHECK=SEVERITY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    pub check: Check,
+    pub severity: Severity,
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (check, severity) = s
+            .split_once('=')
+            .ok_or_else(|| Error::InvalidLevel(s.to_string()))?;
+        Ok(Level {
+            check: check.parse()?,
+            severity: severity.parse()?,
+        })
+    }
+}
+
+/// The severities to assign to each check
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    levels: HashMap<Check, Severity>,
+}
+
+impl Config {
+    pub fn new(levels: &[Level]) -> Config {
+        Config {
+            levels: levels.iter().map(|l| (l.check, l.severity)).collect(),
+        }
+    }
+
+    pub fn severity(&self, check: Check) -> Severity {
+        self.levels
+            .get(&check)
+            .copied()
+            .unwrap_or_else(|| check.default_severity())
+    }
+}
+
+/// A problem found by a check
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub check: Check,
+    pub tag: String,
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{severity}[{check}]: {location}: {message}",
+            severity = self.severity,
+            check = self.check,
+            location = self.location,
+            message = self.message
+        )
+    }
+}
+
+/// A summary of the problems found by the checks
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    pub errors: usize,
+    pub warnings: usize,
+    /// Whether any problem is severe enough to fail the check
+    pub failed: bool,
+    pub diagnostics: &'a [Diagnostic],
+}
+
+impl<'a> Summary<'a> {
+    /// `new(diagnostics, fail_on)` summarizes the `diagnostics`, which fail the
+    /// check if any has a severity of at least `fail_on`
+    pub fn new(diagnostics: &'a [Diagnostic], fail_on: Severity) -> Self {
+        let count = |severity| {
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == severity)
+                .count()
+        };
+        Summary {
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+            failed: diagnostics.iter().any(|d| d.severity >= fail_on),
+            diagnostics,
+        }
+    }
+}
+
+/// A reference from a `unit` to a logical unit that does not exist
+#[derive(Debug, PartialEq, Eq)]
+pub struct DanglingRef<'a> {
+    pub unit: &'a LogicalUnit,
+    pub reference: &'a Reference,
+}
+
+impl DanglingRef<'_> {
+    /// Where the reference is made, or else where the unit making it is
+    /// defined, if the line of the reference is unknown
+    pub fn location(&self) -> String {
+        match self.reference.line {
+            Some(line) => self.unit.location_at(Some(line)),
+            None => self.unit.location(),
+        }
+    }
+}
+
+impl fmt::Display for DanglingRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{location}: {unit} refers to missing unit {reference}",
+            location = self.location(),
+            unit = self.unit.id,
+            reference = self.reference.id
+        )
+    }
+}
+
+/// `dangling_refs(units)` is all the references made by the `units` to logical
+/// units not included among the `units`
+pub fn dangling_refs(units: &[LogicalUnit]) -> Vec<DanglingRef<'_>> {
+    let ids: HashSet<&Id> = units.iter().map(|u| &u.id).collect();
+    units
+        .iter()
+        .flat_map(|unit| {
+            unit.references
+                .iter()
+                .filter(|reference| !ids.contains(&reference.id))
+                .map(move |reference| DanglingRef { unit, reference })
+        })
+        .collect()
+}
+
+fn is_implementation(unit: &LogicalUnit) -> bool {
+    matches!(unit.kind, Kind::Implementation | Kind::Verification)
+}
+
+/// `diagnostics(&config, units)` is every problem found by running all the
+/// checks over the `units`, ordered by decreasing severity. Problems from
+/// checks configured to `Severity::Allow` are omitted.
+pub fn diagnostics(config: &Config, units: &[LogicalUnit]) -> Vec<Diagnostic> {
+    let mut found = Vec::new();
+    let mut report = |check: Check, unit: &LogicalUnit, location: String, message: String| {
+        let severity = config.severity(check);
+        if severity > Severity::Allow {
+            found.push(Diagnostic {
+                severity,
+                check,
+                tag: unit.id.to_string(),
+                location,
+                message,
+            })
+        }
+    };
+
+    let by_id = logical_unit::occurrences(units);
+    let is_defined = |id: &Id| by_id.get(id).is_some_and(|units| units[0].is_definition());
+
+    // Only the definitions of a tag can be duplicated, since a tag may occur
+    // in any number of implementations
+    for (id, occurrences) in by_id.iter() {
+        let definitions: Vec<&&LogicalUnit> =
+            occurrences.iter().filter(|u| u.is_definition()).collect();
+        if let Some((first, rest)) = definitions.split_first() {
+            for dup in rest {
+                let message = format!("duplicates unit {} at {}", id, first.location());
+                report(Check::DuplicateUnit, dup, dup.location(), message)
+            }
+        }
+    }
+
+    for unit in units {
+        // An occurrence of a defined tag implements the definition itself
+        if is_implementation(unit) && is_defined(&unit.id) {
+            continue;
+        }
+        if let Some(parent) = unit.parent_id().filter(|p| !by_id.contains_key(p)) {
+            if is_implementation(unit) {
+                let message = format!("{} implements unknown spec {}", unit.id, parent);
+                report(Check::UnknownSpec, unit, unit.location(), message)
+            } else {
+                let message = format!("{} is missing its parent {}", unit.id, parent);
+                report(Check::OrphanUnit, unit, unit.location(), message)
+            }
+        }
+    }
+
+    for d in dangling_refs(units) {
+        let message = format!("{} refers to missing unit {}", d.unit.id, d.reference.id);
+        report(Check::DanglingReference, d.unit, d.location(), message)
+    }
+
+    // A repo that owns a prefix should define all, and only, the units with
+    // that prefix
+    let owners: HashMap<&str, &Repo> = units
+        .iter()
+        .filter_map(|u| u.repo.as_ref())
+        .filter_map(|r| r.prefix().map(|prefix| (prefix, r)))
+        .collect();
+    for unit in units.iter().filter(|u| u.is_definition()) {
+        let prefix = unit.id.prefix();
+        let repo = match &unit.repo {
+            Some(repo) => repo,
+            None => continue,
+        };
+        if let Some(owner) = owners.get(prefix).filter(|o| o.path() != repo.path()) {
+            let message = format!(
+                "{} is defined outside of {}, which owns {}",
+                unit.id, owner, prefix
+            );
+            report(Check::ForeignDefinition, unit, unit.location(), message)
+        } else if let Some(owned) = repo.prefix().filter(|owned| *owned != prefix) {
+            let message = format!(
+                "{} is defined in {}, which only owns {}",
+                unit.id, repo, owned
+            );
+            report(Check::ForeignDefinition, unit, unit.location(), message)
+        }
+    }
+
+    // A requirement is implemented if an implementation is one of its
+    // children or refers to it. Only requirements with no sub-requirements
+    // are expected to be implemented directly.
+    let mut implemented: HashSet<Id> = HashSet::new();
+    let mut refined: HashSet<Id> = HashSet::new();
+    for unit in units {
+        if is_implementation(unit) {
+            implemented.insert(unit.id.clone());
+            implemented.extend(unit.parent_id());
+            implemented.extend(unit.references.iter().map(|r| r.id.clone()));
+        } else if let Some(parent) = unit.parent_id() {
+            refined.insert(parent);
+        }
+    }
+    for (id, units) in by_id.iter() {
+        let unit = units[0];
+        if unit.kind == Kind::Requirement && !refined.contains(id) && !implemented.contains(id) {
+            let message = format!("{} has no implementation", id);
+            report(
+                Check::UnimplementedRequirement,
+                unit,
+                unit.location(),
+                message,
+            )
+        }
+    }
+
+    found.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.cmp(b)));
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unit(kind: Kind, tag: &str, content: &str) -> LogicalUnit {
+        LogicalUnit::new(None, None, None, kind, tag, content).unwrap()
+    }
+
+    #[test]
+    fn finds_dangling_refs() {
+        let mut located = LogicalUnit::new(
+            None,
+            Some(std::path::Path::new("spec.md")),
+            Some(3),
+            Kind::Requirement,
+            "BAZ.1",
+            "See [QUX.1]",
+        )
+        .unwrap();
+        located.references[0].line = Some(4);
+        let units = vec![
+            unit(Kind::Requirement, "FOO.1", "See [BAR.1]"),
+            unit(Kind::Requirement, "BAR.1", "See [FOO.1] and [BAZ.1]"),
+            located,
+        ];
+        let actual: Vec<String> = dangling_refs(&units)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            actual,
+            vec!["spec.md:4: BAZ.1 refers to missing unit QUX.1"]
+        );
+    }
+
+    #[test]
+    fn runs_all_checks() {
+        let units = vec![
+            unit(Kind::Requirement, "FOO.1", "Foo"),
+            unit(Kind::Requirement, "FOO.1::BAR.1", "Bar, see [FIZ.1]"),
+            unit(Kind::Requirement, "FOO.1::BAZ.1", "Baz"),
+            unit(Kind::Requirement, "FOO.1::BAZ.1", "Duplicate baz"),
+            unit(Kind::Requirement, "ORPHAN.1::BOP.1", "Bop"),
+            unit(Kind::Implementation, "FOO.1::BAR.1::IMPL.1", ""),
+            unit(Kind::Implementation, "NO-SPEC.1::IMPL.1", ""),
+            unit(Kind::Requirement, "QUX.1", "Qux"),
+            unit(Kind::Implementation, "QUX.1", "fn qux"),
+            unit(Kind::Verification, "QUX.1", "fn test_qux"),
+        ];
+        let actual: Vec<String> = diagnostics(&Config::default(), &units)
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        let expected = vec![
+            "error[duplicate-unit]: <unknown>: duplicates unit FOO.1::BAZ.1 at <unknown>",
+            "error[dangling-reference]: <unknown>: FOO.1::BAR.1 refers to missing unit FIZ.1",
+            "error[unknown-spec]: <unknown>: NO-SPEC.1::IMPL.1 implements unknown spec NO-SPEC.1",
+            "warning[orphan-unit]: <unknown>: ORPHAN.1::BOP.1 is missing its parent ORPHAN.1",
+            "warning[unimplemented-requirement]: <unknown>: FOO.1::BAZ.1 has no implementation",
+            "warning[unimplemented-requirement]: <unknown>: ORPHAN.1::BOP.1 has no implementation",
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn allowed_checks_are_not_reported() {
+        let units = vec![unit(Kind::Requirement, "FOO.1", "Foo")];
+        let config = Config::new(&["unimplemented-requirement=allow".parse().unwrap()]);
+        assert!(diagnostics(&config, &units).is_empty());
+    }
+
+    #[test]
+    fn summarizes_diagnostics() {
+        let units = vec![
+            unit(Kind::Requirement, "FOO.1", "Foo, see [BAR.1]"),
+            unit(Kind::Requirement, "FOO.1", "Duplicate foo"),
+        ];
+        let diagnostics = diagnostics(&Config::default(), &units);
+        let summary = Summary::new(&diagnostics, Severity::Error);
+        assert_eq!((summary.errors, summary.warnings), (2, 1));
+        assert!(summary.failed);
+        let summary = Summary::new(&diagnostics[..0], Severity::Warning);
+        assert!(!summary.failed);
+    }
+
+    #[test]
+    fn reports_units_defined_outside_of_their_owners() {
+        let repo = |prefix: Option<&str>| {
+            let dir = tempfile::tempdir().unwrap();
+            if let Some(prefix) = prefix {
+                let config = format!("prefix = \"{}\"", prefix);
+                std::fs::write(dir.path().join(".kontxt.toml"), config).unwrap();
+            }
+            let repo = Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+            (dir, repo)
+        };
+        let (_foo_dir, foo) = repo(Some("FOO"));
+        let (_other_dir, other) = repo(None);
+        let def = |repo: &Repo, kind, tag: &str| {
+            LogicalUnit::new(Some(repo.clone()), None, None, kind, tag, "").unwrap()
+        };
+        let units = vec![
+            def(&foo, Kind::Requirement, "FOO.1"),
+            def(&foo, Kind::Requirement, "BAR.1"),
+            def(&other, Kind::Requirement, "FOO.1::BAZ.1"),
+            def(&other, Kind::Implementation, "FOO.1::IMPL.1"),
+            def(&other, Kind::Requirement, "QUX.1"),
+        ];
+        let config = Config::new(&["unimplemented-requirement=allow".parse().unwrap()]);
+        let actual: Vec<String> = diagnostics(&config, &units)
+            .iter()
+            .map(|d| d.message.clone())
+            .collect();
+        let expected = vec![
+            format!("BAR.1 is defined in {}, which only owns FOO", foo),
+            format!("FOO.1::BAZ.1 is defined outside of {}, which owns FOO", foo),
+        ];
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,129 @@
+//! This module implements the `check` subcommand
+//!
+//! The `check` subcommand synchronizes the current context (or parses a set of
+//! paths, without any context) and runs all of the consistency checks defined
+//! in [`check`] over the resulting logical units.
+//!
+//! [`check`]: crate::check
+
+use {
+    crate::{
+        artifact::Artifact,
+        check::{self, Config, Level, Severity, Summary},
+        cmd::{self, format::Format},
+        db, locations,
+        logical_unit::LogicalUnit,
+    },
+    anyhow::Result,
+    rayon::{prelude::*, ThreadPool},
+    std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+    },
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Check failed: {0} problems with severity {1} or higher")]
+    Failed(usize, Severity),
+}
+
+fn units_of_path(path: &Path, pool: &ThreadPool) -> Result<Vec<LogicalUnit>> {
+    let files = locations::find_all_supported_source_files(path)?;
+    let artifacts = pool.install(|| {
+        files
+            .par_iter()
+            .map(|file| Artifact::from_file(Path::new(""), None, file))
+            .collect::<Result<Vec<Artifact>>>()
+    })?;
+    Ok(artifacts
+        .into_iter()
+        .flat_map(|artifact| artifact.logical_units)
+        .collect())
+}
+
+fn has_duplicate_definitions(units: &[LogicalUnit]) -> bool {
+    let mut ids = HashSet::new();
+    !units
+        .iter()
+        .filter(|u| u.is_definition())
+        .all(|u| ids.insert(&u.id))
+}
+
+// Syncs the current context, returning all of the units found in its repos.
+//
+// Since a context with duplicate definitions of units cannot be recorded in the db, the
+// context is left as it is in that case, so the duplicates can be reported.
+fn units_of_context(pool: &ThreadPool) -> Result<Vec<LogicalUnit>> {
+    let mut conn = db::connection()?;
+    db::context::current(&conn)?.ok_or(db::Error::NoContext)?;
+    let tx = conn.transaction()?;
+    let conn = &tx;
+
+    let mut updates = Vec::new();
+    let mut units = Vec::new();
+    for repo in db::repo::get_all_in_context(conn)? {
+        let update = cmd::repo::sync_repo(conn, repo, false, pool)?;
+        units.extend(update.units(conn)?);
+        updates.push(update);
+    }
+
+    if has_duplicate_definitions(&units) {
+        // Dropping the transaction rolls back the syncing of the repos, so
+        // their records stay consistent with their units
+        log::warn!("not syncing the context, since it contains duplicate units");
+        return Ok(units);
+    }
+    for update in updates {
+        update.apply(conn)?;
+    }
+    tx.commit()?;
+
+    Ok(units)
+}
+
+fn report_human(summary: &Summary) {
+    for d in summary.diagnostics {
+        println!("{}", d)
+    }
+    println!("errors: {}, warnings: {}", summary.errors, summary.warnings);
+}
+
+/// Check the units of the current context, or else of the `paths`, reporting
+/// problems according to the `levels`, serialized in the `format`. Files are
+/// parsed by a pool of `jobs` workers.
+///
+/// Fails if any problem with a severity of at least `fail_on` is found.
+pub fn run(
+    paths: &[PathBuf],
+    levels: &[Level],
+    fail_on: Severity,
+    format: Option<Format>,
+    jobs: usize,
+) -> Result<()> {
+    let pool = cmd::jobs::pool(jobs)?;
+    let units = if paths.is_empty() {
+        units_of_context(&pool)?
+    } else {
+        let mut units = Vec::new();
+        for path in paths {
+            units.extend(units_of_path(path, &pool)?);
+        }
+        units
+    };
+
+    let diagnostics = check::diagnostics(&Config::new(levels), &units);
+    let summary = Summary::new(&diagnostics, fail_on);
+    match format {
+        None => report_human(&summary),
+        Some(fmt) => fmt.summary(&summary)?,
+    }
+
+    let failures = diagnostics.iter().filter(|d| d.severity >= fail_on).count();
+    if failures > 0 {
+        Err(Error::Failed(failures, fail_on).into())
+    } else {
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
 use {
-    crate::{cmd, context::Context, db},
+    crate::{
+        cmd::{self, prompt},
+        context::Context,
+        db,
+        repo::Repo,
+    },
     anyhow::Result,
     thiserror::Error,
 };
@@ -59,11 +64,51 @@ fn switch(name: String) -> Result<()> {
     db::context::set(&conn, name)
 }
 
+fn rename(old: String, new: String) -> Result<()> {
+    let conn = db::connection()?;
+    match db::context::rename(&conn, &old, &new) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            if err
+                .to_string()
+                .contains("UNIQUE constraint failed: context.name")
+            {
+                Err(Error::ContextExists(new).into())
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+fn delete(name: String, yes: bool) -> Result<()> {
+    let mut conn = db::connection()?;
+    if db::context::get(&conn, &name)?.is_none() {
+        return Err(db::Error::NonexistentContext(name).into());
+    }
+    prompt::confirm(
+        &format!("Delete the context {} along with all of its units?", name),
+        yes,
+    )?;
+    let tx = conn.transaction()?;
+    let unused = db::context::delete(&tx, &name)?;
+    let remaining = db::repo::get_all(&tx)?;
+    tx.commit()?;
+    // Clones are only removed once the repo is forgotten by the db
+    Repo::remove_clones(&unused, &remaining)
+}
+
 pub fn run(ctx: cmd::opt::Context) -> Result<()> {
     match ctx {
-        cmd::opt::Context::Sync {} => cmd::sync::run(),
+        cmd::opt::Context::Sync {
+            deny_dangling,
+            query_upstream,
+            jobs,
+        } => cmd::sync::run(deny_dangling, query_upstream, jobs),
         cmd::opt::Context::New { name } => new(name),
         cmd::opt::Context::List {} => list(),
         cmd::opt::Context::Switch { name } => switch(name),
+        cmd::opt::Context::Rename { old, new } => rename(old, new),
+        cmd::opt::Context::Delete { name, yes } => delete(name, yes),
     }
 }
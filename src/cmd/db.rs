@@ -0,0 +1,41 @@
+//! Inspect and migrate the app's db
+
+use {
+    crate::{cmd::opt, db},
+    anyhow::Result,
+};
+
+fn status() -> Result<()> {
+    let conn = db::open()?;
+    let version = db::version(&conn)?;
+    println!("version: {}", version);
+    println!("latest:  {}", db::MIGRATIONS.len());
+    for (n, migration) in db::pending(&conn)?.iter().enumerate() {
+        println!("pending: {} {}", version + n + 1, migration.name)
+    }
+    Ok(())
+}
+
+fn migrate() -> Result<()> {
+    let conn = db::open()?;
+    let version = db::version(&conn)?;
+    let applied = db::migrate(&conn)?;
+    if applied.is_empty() {
+        println!("Up to date at version {}", version)
+    }
+    for (n, migration) in applied.iter().enumerate() {
+        println!(
+            "Migrated to version {}: {}",
+            version + n + 1,
+            migration.name
+        )
+    }
+    Ok(())
+}
+
+pub fn run(opt: opt::Db) -> Result<()> {
+    match opt {
+        opt::Db::Status {} => status(),
+        opt::Db::Migrate {} => migrate(),
+    }
+}
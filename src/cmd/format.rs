@@ -1,7 +1,12 @@
 use {
-    crate::logical_unit::LogicalUnit,
+    crate::{
+        check::Summary,
+        logical_unit::{Id, Kind, LogicalUnit},
+        repo::{Location, Repo},
+    },
     anyhow::Result,
-    std::{fmt, io},
+    serde::Serialize,
+    std::{fmt, io, path::PathBuf},
 };
 
 impl fmt::Display for ParseFormatError {
@@ -14,6 +19,41 @@ impl fmt::Display for ParseFormatError {
 #[derive(Debug, Clone)]
 pub struct ParseFormatError(String);
 
+/// The CSV serialization of a [LogicalUnit]
+///
+/// CSV records must all have the same number of fields, so the references of
+/// the unit are rendered into a single field, separated by spaces, and only the
+/// location of the unit's repo is included.
+#[derive(Serialize)]
+struct CsvUnit<'a> {
+    id: &'a Id,
+    kind: &'a Kind,
+    repo: Option<&'a Location>,
+    file: &'a Option<PathBuf>,
+    line: Option<u64>,
+    content: &'a str,
+    references: String,
+}
+
+impl<'a> From<&'a LogicalUnit> for CsvUnit<'a> {
+    fn from(unit: &'a LogicalUnit) -> Self {
+        CsvUnit {
+            id: &unit.id,
+            kind: &unit.kind,
+            repo: unit.repo.as_ref().map(Repo::location),
+            file: &unit.file,
+            line: unit.line,
+            content: &unit.content,
+            references: unit
+                .references
+                .iter()
+                .map(|r| r.id.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
 /// Formats supported for rendering parsed requirement data
 #[derive(Debug)]
 pub enum Format {
@@ -24,7 +64,22 @@ pub enum Format {
 impl Format {
     pub fn units(&self, mut lus: Vec<LogicalUnit>) -> Result<()> {
         lus.sort();
+        match self {
+            Format::Csv => self.records(&lus.iter().map(CsvUnit::from).collect::<Vec<_>>()),
+            Format::Json => self.records(&lus),
+        }
+    }
+
+    /// Renders the `summary` of a check. JSON is rendered as a single object,
+    /// while CSV only has a record for each diagnostic.
+    pub fn summary(&self, summary: &Summary) -> Result<()> {
+        match self {
+            Format::Csv => self.records(summary.diagnostics),
+            Format::Json => self.records(&[summary]),
+        }
+    }
 
+    fn records<T: Serialize>(&self, records: &[T]) -> Result<()> {
         match self {
             Format::Csv => {
                 // See https://docs.rs/csv/1.1.3/csv/tutorial/index.html#writing-csv
@@ -42,10 +97,11 @@ impl Format {
                 //     "content",
                 //     "references",
                 // ])?;
-                lus.iter()
+                records
+                    .iter()
                     .try_for_each(|x| wtr.serialize(x).map_err(|e| e.into()))
             }
-            Format::Json => lus.iter().try_for_each(|x| {
+            Format::Json => records.iter().try_for_each(|x| {
                 serde_json::to_string(x)
                     .map_err(|e| e.into())
                     .map(|x| println!("{}", x))
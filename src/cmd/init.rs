@@ -17,12 +17,15 @@ pub enum InitError {
 }
 
 /// Returns `Ok(conn)` if the db connection `conn` can be iniated with the app's
-/// db. If needed, the config dir is created.
+/// db. If needed, the config dir is created and the db is initialized.
+///
+/// The db of an existing config dir is not migrated, so that its state can be
+/// inspected first.
 pub(super) fn ensured() -> Result<sql::Connection> {
     let dir = locations::tracer_dir()?;
     if dir.exists() {
         // If the directory exists, we assume proper initialization
-        let conn = db::connection()?;
+        let conn = db::open()?;
         Ok(conn)
     } else {
         fs::create_dir_all(dir.clone()).map_err(|e| InitError::Home(e.to_string()))?;
@@ -31,7 +34,6 @@ pub(super) fn ensured() -> Result<sql::Connection> {
             .into_string()
             .unwrap_or_else(|_| "<cannot be displayed>".into());
         let conn = db::connection()?;
-        db::init(&conn)?;
         println!("Initialized into {}", location);
         Ok(conn)
     }
@@ -0,0 +1,7 @@
+use {anyhow::Result, rayon::ThreadPool};
+
+/// `pool(jobs)` is a pool of `jobs` workers, or of one worker per CPU if
+/// `jobs` is 0
+pub fn pool(jobs: usize) -> Result<ThreadPool> {
+    Ok(rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?)
+}
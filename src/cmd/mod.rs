@@ -8,12 +8,20 @@ pub mod opt;
 /// Utility for serialized output formats
 mod format;
 
+/// Utility for running work on a pool of workers
+mod jobs;
+
+/// Utility for confirming destructive operations
+mod prompt;
+
 /// ## Subcommand executors
 ///
 /// Each of the following modules contains an executors for the corresponding
 /// subcommand defined in `opt`. Each module exports a `run` function for this
 /// purpose.
+mod check;
 mod context;
+mod db;
 mod file;
 mod graph;
 mod init;
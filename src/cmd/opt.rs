@@ -1,5 +1,5 @@
 //! CLI specification
-use crate::cmd;
+use crate::{check, cmd, hosting, repo};
 use anyhow::Result;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -29,6 +29,46 @@ pub enum Cmd {
 
     /// Context views and reports
     Generate(Generate),
+
+    /// Database management
+    Db(Db),
+
+    /// Check the consistency of the current context
+    ///
+    /// Synchronizes the current context and checks its logical units for
+    /// duplicate units, orphan units, dangling references, unimplemented
+    /// requirements, and implementations of unknown specs. If paths are given,
+    /// the logical units found in them are checked instead, without using any
+    /// context.
+    Check {
+        /// Files or directories to check instead of the current context
+        #[structopt(parse(from_os_str))]
+        paths: Vec<PathBuf>,
+
+        /// Set the severity of a check, given as CHECK=SEVERITY
+        ///
+        /// The checks are duplicate-unit, orphan-unit, dangling-reference,
+        /// unimplemented-requirement, unknown-spec, and foreign-definition.
+        /// The severities are allow, warning, and error.
+        #[structopt(short, long = "level", number_of_values = 1, parse(try_from_str))]
+        levels: Vec<check::Level>,
+
+        /// Fail if any problem with at least this severity is found
+        #[structopt(long, default_value = "error", parse(try_from_str))]
+        fail_on: check::Severity,
+
+        /// Serialization to use when reporting problems.
+        ///
+        /// When absent, the output is a line per problem followed by a summary,
+        /// optimized for human readability. As JSON, the output is a single
+        /// object with the summary and the problems.
+        #[structopt(short, long, parse(try_from_str))]
+        format: Option<cmd::format::Format>,
+
+        /// The number of files to parse in parallel, defaulting to one per CPU
+        #[structopt(short, long, default_value = "0")]
+        jobs: usize,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -42,7 +82,20 @@ pub enum Context {
     ///
     /// Update the logical units for the current context by rescanning all
     /// assocaited repositories.
-    Sync {},
+    Sync {
+        /// Fail if any unit refers to a unit missing from the context
+        #[structopt(long)]
+        deny_dangling: bool,
+
+        /// Ask the upstream of each repo for its default branch, if it isn't
+        /// known from the upstream's last fetched `HEAD`
+        #[structopt(long)]
+        query_upstream: bool,
+
+        /// The number of files to parse in parallel, defaulting to one per CPU
+        #[structopt(short, long, default_value = "0")]
+        jobs: usize,
+    },
 
     /// Context listing
     List {},
@@ -52,6 +105,26 @@ pub enum Context {
         /// The name of the context to switch to
         name: String,
     },
+
+    /// Context renaming
+    Rename {
+        /// The name of the context to rename
+        old: String,
+        /// The new name of the context
+        new: String,
+    },
+
+    /// Context deletion
+    ///
+    /// Delete a context along with all of its logical units. Repos that are
+    /// not used by any other context are forgotten.
+    Delete {
+        /// The name of the context to delete
+        name: String,
+        /// Do not ask for confirmation
+        #[structopt(short, long)]
+        yes: bool,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -81,10 +154,52 @@ pub enum Repo {
     List {},
 
     /// Add a repoistory to the current context
+    ///
+    /// Repos given by the URL of a git repo are cloned into the kontxt
+    /// directory, and fetched when the context is synced.
     Add {
-        /// The path to the repo to be added
-        #[structopt(parse(from_os_str))]
-        path: PathBuf,
+        /// The path to the repo to be added, or the URL of a git repo
+        repo: String,
+
+        /// Pin the repo to a branch, tag, or commit in the current context,
+        /// reading its units from that revision instead of its working tree
+        #[structopt(long)]
+        rev: Option<String>,
+
+        /// Fail if any unit refers to a unit missing from the context
+        #[structopt(long)]
+        deny_dangling: bool,
+
+        /// Ask the upstream of the repo for its default branch, if it isn't
+        /// known from the upstream's last fetched `HEAD`
+        #[structopt(long)]
+        query_upstream: bool,
+
+        /// Set the kind of the units found in files matching a glob, given as
+        /// GLOB=KIND (e.g., `specs/**/*.md=model`)
+        #[structopt(long = "kind", number_of_values = 1)]
+        kinds: Vec<repo::KindOverride>,
+
+        /// Set the template for links to the files of the repo, overriding
+        /// the layout of its host (e.g., `{repo}/src/{rev}/{path}#L{line}`)
+        #[structopt(long)]
+        url_template: Option<hosting::UrlTemplate>,
+
+        /// The number of files to parse in parallel, defaulting to one per CPU
+        #[structopt(short, long, default_value = "0")]
+        jobs: usize,
+    },
+
+    /// Remove a repository from the current context
+    ///
+    /// The logical units of the repo are removed from the context, and the
+    /// repo is forgotten if no other context uses it.
+    Remove {
+        /// The path or URL of the repo to be removed
+        repo: String,
+        /// Do not ask for confirmation
+        #[structopt(short, long)]
+        yes: bool,
     },
 }
 
@@ -114,6 +229,43 @@ pub enum Unit {
         #[structopt(short, long, parse(try_from_str))]
         format: Option<cmd::format::Format>,
     },
+
+    /// List the units referred to by a unit
+    Refs {
+        /// The tag of the referring unit
+        tag: String,
+
+        /// Serialization to use when listing the units.
+        ///
+        /// When absent, the output is a tab delimited readible with a synopsis
+        /// of each unit, optimized for human readability.
+        #[structopt(short, long, parse(try_from_str))]
+        format: Option<cmd::format::Format>,
+    },
+
+    /// List the units which refer to a unit
+    Backrefs {
+        /// The tag of the referenced unit
+        tag: String,
+
+        /// Serialization to use when listing the units.
+        ///
+        /// When absent, the output is a tab delimited readible with a synopsis
+        /// of each unit, optimized for human readability.
+        #[structopt(short, long, parse(try_from_str))]
+        format: Option<cmd::format::Format>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Db {
+    /// Show the version of the database schema and any pending migrations
+    Status {},
+
+    /// Apply all pending migrations to the database
+    ///
+    /// Migrations are also applied whenever the database is used.
+    Migrate {},
 }
 
 #[derive(Debug, StructOpt)]
@@ -143,5 +295,13 @@ pub fn run() -> Result<()> {
         // TODO Clean up
         Cmd::Generate(Generate::Graph { format }) => cmd::graph::run(format),
         Cmd::Generate(Generate::Site {}) => cmd::site::run(),
+        Cmd::Db(opt) => cmd::db::run(opt),
+        Cmd::Check {
+            paths,
+            levels,
+            fail_on,
+            format,
+            jobs,
+        } => cmd::check::run(&paths, &levels, fail_on, format, jobs),
     }
 }
@@ -17,7 +17,7 @@ use {
 /// to `stdout`.
 pub fn run(path: &Path, format: Format) -> Result<()> {
     // TODO Get repo from context?
-    Artifact::from_file(None, path)
+    Artifact::from_file(Path::new(""), None, path)
         .map(|a| a.logical_units.iter().cloned().collect())
         .and_then(|units| format.units(units))
 }
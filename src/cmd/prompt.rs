@@ -0,0 +1,29 @@
+use {
+    anyhow::Result,
+    std::io::{self, BufRead, Write},
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+/// `confirm(question, yes)` asks for confirmation of the `question` on
+/// `stderr`, reading the answer from `stdin`, unless `yes` is `true`
+///
+/// Fails with `Error::Cancelled` unless the answer is yes.
+pub fn confirm(question: &str, yes: bool) -> Result<()> {
+    if yes {
+        return Ok(());
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(Error::Cancelled.into()),
+    }
+}
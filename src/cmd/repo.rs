@@ -1,9 +1,20 @@
 use {
-    crate::{artifact::Artifact, cmd::opt, db, graph, locations, repo::Repo},
+    crate::{
+        artifact::Artifact,
+        check,
+        cmd::{jobs, opt, prompt},
+        db, graph,
+        hosting::UrlTemplate,
+        locations,
+        logical_unit::{Id, LogicalUnit},
+        repo::{self, KindOverride, Pin, Repo, Storage},
+    },
     anyhow::Result,
+    rayon::{prelude::*, ThreadPool},
     rusqlite as sql,
     std::{
-        env, fs,
+        collections::BTreeMap,
+        fmt, fs,
         path::{Path, PathBuf},
     },
     thiserror::Error,
@@ -14,7 +25,11 @@ pub enum Error {
     #[error("No repo found at path {0}")]
     RepoNotFound(PathBuf),
     #[error("The repo {0} is already registered in the current context")]
-    RepoExists(Repo),
+    RepoExists(Box<Repo>),
+    #[error("The repo {0} is not registered in the current context")]
+    RepoNotInContext(String),
+    #[error("References to missing units:\n{}", .0.join("\n"))]
+    DanglingReferences(Vec<String>),
 }
 
 fn list() -> Result<()> {
@@ -23,61 +38,393 @@ fn list() -> Result<()> {
     repos.sort();
 
     for repo in repos {
-        println!("  {}", repo)
+        let storage = match repo.storage() {
+            Storage::Git => "".to_string(),
+            storage => format!(" ({})", storage),
+        };
+        match db::repo::pin(&conn, &repo)? {
+            Some(pin) => println!("  {}@{}{}", repo, pin, storage),
+            None => println!("  {}{}", repo, storage),
+        }
     }
 
     Ok(())
 }
 
-fn load_units_from_file(conn: &sql::Connection, repo: &Repo, path: &Path) -> Result<()> {
-    Artifact::from_file(Some(repo), path)?
-        .logical_units
-        .iter()
-        .try_for_each(|unit| db::unit::add(conn, repo, unit))
+/// A summary of the changes to the units of a repo made by an [`Update`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+}
+
+impl Summary {
+    // Units are identified by the file they are found in and their tag, and
+    // are changed if any of the units so identified differ.
+    fn of_units(old: &[LogicalUnit], new: &[LogicalUnit]) -> Summary {
+        type Keyed<'a> = BTreeMap<(Option<PathBuf>, Id), Vec<&'a LogicalUnit>>;
+        fn by_key(units: &[LogicalUnit]) -> Keyed<'_> {
+            let mut map: Keyed = BTreeMap::new();
+            for u in units {
+                map.entry((u.file.clone(), u.id.clone()))
+                    .or_default()
+                    .push(u);
+            }
+            for units in map.values_mut() {
+                units.sort();
+            }
+            map
+        }
+        let (old, new) = (by_key(old), by_key(new));
+        Summary {
+            added: new.keys().filter(|k| !old.contains_key(k)).count(),
+            changed: new
+                .iter()
+                .filter(|(k, units)| old.get(k).is_some_and(|o| o != *units))
+                .count(),
+            removed: old.keys().filter(|k| !new.contains_key(k)).count(),
+        }
+    }
 }
 
-pub fn load_units_from_repo(conn: &sql::Connection, repo: &Repo) -> Result<()> {
-    env::set_current_dir(repo.path())?;
-    for path in locations::find_all_supported_source_files(&repo.path())? {
-        let path = path.strip_prefix(repo.path())?;
-        load_units_from_file(conn, repo, &path)?
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} changed, {} removed",
+            self.added, self.changed, self.removed
+        )
     }
+}
+
+// An artifact that is new or has changed since the repo was last synced
+struct Parsed {
+    path: PathBuf,
+    hash: String,
+    units: Vec<LogicalUnit>,
+}
+
+/// The changes to the artifacts of a repo since it was last synced
+///
+/// Only the artifacts with contents that differ from those recorded in the db
+/// are parsed, and the db is only written to when the update is applied.
+pub struct Update {
+    repo: Repo,
+    // The artifacts that were added or changed
+    parsed: Vec<Parsed>,
+    // The recorded artifacts that have been removed or changed
+    stale: Vec<PathBuf>,
+    // The recorded artifacts that are unchanged
+    unchanged: Vec<PathBuf>,
+}
+
+/// `hash_of_file(path)` is the git blob id of the contents of the file at
+/// `path`
+fn hash_of_file(path: &Path) -> Result<String> {
+    Ok(git2::Oid::hash_file(git2::ObjectType::Blob, path)?.to_string())
+}
+
+impl Update {
+    /// `Update::of_repo(&conn, &repo, pin, reparse, &pool)` finds the
+    /// artifacts of the `repo` that have changed since it was last synced in
+    /// the current context, parsing all of them if `reparse` is `true`
+    ///
+    /// The artifacts are read from the tree of the commit the repo is pinned
+    /// to by the `pin`, if any, or else from its working tree. They are parsed
+    /// by the workers of the `pool`.
+    pub fn of_repo(
+        conn: &sql::Connection,
+        repo: &Repo,
+        pin: Option<&Pin>,
+        reparse: bool,
+        pool: &ThreadPool,
+    ) -> Result<Update> {
+        let mut recorded = db::artifact::hashes(conn, repo)?;
+        let changed = |path: &Path, hash: &String| reparse || recorded.get(path) != Some(hash);
+
+        // Each artifact is paired with the units found in it, if it is new
+        // or has changed
+        let artifacts = match pin {
+            None => {
+                let root = repo.root();
+                let mut files = Vec::new();
+                for file in locations::find_all_files(&repo.path())? {
+                    let path = file.strip_prefix(&root)?.to_path_buf();
+                    if repo.parses(&path) {
+                        files.push((file, path))
+                    }
+                }
+                pool.install(|| {
+                    files
+                        .into_par_iter()
+                        .map(|(file, path)| {
+                            let hash = hash_of_file(&file)?;
+                            let units = if changed(&path, &hash) {
+                                let artifact = Artifact::from_file(&root, Some(repo), &path)?;
+                                Some(artifact.logical_units.into_iter().collect())
+                            } else {
+                                None
+                            };
+                            Ok((path, hash, units))
+                        })
+                        .collect::<Result<Vec<(PathBuf, String, Option<Vec<LogicalUnit>>)>>>()
+                })?
+            }
+            Some(pin) => {
+                // The tree can't be shared between the workers, so the
+                // contents of the changed artifacts are read up front
+                let tree = repo.tree(pin)?;
+                let mut files = Vec::new();
+                for (path, hash) in tree.files()? {
+                    if repo.parses(&path) {
+                        let contents = if changed(&path, &hash) {
+                            Some(tree.read(&path)?)
+                        } else {
+                            None
+                        };
+                        files.push((path, hash, contents))
+                    }
+                }
+                pool.install(|| {
+                    files
+                        .into_par_iter()
+                        .map(|(path, hash, contents)| {
+                            let units = match contents {
+                                Some(contents) => {
+                                    let artifact =
+                                        Artifact::from_contents(Some(repo), &path, &contents)?;
+                                    Some(artifact.logical_units.into_iter().collect())
+                                }
+                                None => None,
+                            };
+                            Ok((path, hash, units))
+                        })
+                        .collect::<Result<Vec<(PathBuf, String, Option<Vec<LogicalUnit>>)>>>()
+                })?
+            }
+        };
+
+        let mut update = Update {
+            repo: repo.clone(),
+            parsed: Vec::new(),
+            stale: Vec::new(),
+            unchanged: Vec::new(),
+        };
+        for (path, hash, units) in artifacts {
+            let previous = recorded.remove(&path);
+            match units {
+                None => update.unchanged.push(path),
+                Some(units) => {
+                    if previous.is_some() {
+                        update.stale.push(path.clone())
+                    }
+                    update.parsed.push(Parsed { path, hash, units })
+                }
+            }
+        }
+        // Whatever is left was recorded but no longer exists
+        update.stale.extend(recorded.into_keys());
+
+        Ok(update)
+    }
+
+    /// `update.units(&conn)` is all of the units of the repo once the
+    /// `update` is applied
+    pub fn units(&self, conn: &sql::Connection) -> Result<Vec<LogicalUnit>> {
+        let mut units = Vec::new();
+        for path in self.unchanged.iter() {
+            units.extend(db::unit::of_artifact(conn, &self.repo, path)?);
+        }
+        units.extend(self.new_units());
+        Ok(units)
+    }
+
+    fn new_units(&self) -> impl Iterator<Item = LogicalUnit> + '_ {
+        self.parsed.iter().flat_map(|p| p.units.iter().cloned())
+    }
+
+    /// `update.apply(&conn)` records the changed artifacts of the repo, and
+    /// the units found in them, in the current context, summarizing the
+    /// changes made to its units
+    ///
+    /// The update should be applied within a transaction, so that the context
+    /// is left as it was if any of the changes fail.
+    pub fn apply(self, conn: &sql::Connection) -> Result<Summary> {
+        let mut old = db::unit::untracked(conn, &self.repo)?;
+        for path in self.stale.iter() {
+            old.extend(db::unit::of_artifact(conn, &self.repo, path)?);
+        }
+        let new: Vec<LogicalUnit> = self.new_units().collect();
+
+        db::unit::purge_untracked(conn, &self.repo)?;
+        for path in self.stale.iter() {
+            db::artifact::remove(conn, &self.repo, path)?;
+        }
+        for parsed in self.parsed.iter() {
+            let artifact = db::artifact::add(conn, &self.repo, &parsed.path, &parsed.hash)?;
+            parsed
+                .units
+                .iter()
+                .try_for_each(|unit| db::unit::add(conn, &self.repo, artifact, unit))?;
+        }
+
+        Ok(Summary::of_units(&old, &new))
+    }
+}
+
+/// `sync_repo(&conn, repo, query_upstream, &pool)` updates the `repo` and the
+/// units found in its changed artifacts, returning the update to apply. All
+/// of its artifacts are parsed again if the information recorded for the repo
+/// itself has changed. The upstream of the repo is only asked for its default
+/// branch if `query_upstream` is `true`.
+///
+/// If the repo is pinned to a revision in the current context, it is pinned
+/// to the commit the revision currently resolves to, and configured by the
+/// configuration in that commit.
+pub fn sync_repo(
+    conn: &sql::Connection,
+    mut repo: Repo,
+    query_upstream: bool,
+    pool: &ThreadPool,
+) -> Result<Update> {
+    let recorded = repo.clone();
+    repo.update(query_upstream)?;
+    let pin = match db::repo::pin(conn, &repo)? {
+        Some(pin) => {
+            let pin = repo.resolve(&pin.rev)?;
+            repo.load_config_at(&pin)?;
+            db::repo::set_pin(conn, &repo, Some(&pin))?;
+            Some(pin)
+        }
+        None => None,
+    };
+    db::repo::update(conn, &repo)?;
+    Update::of_repo(conn, &repo, pin.as_ref(), repo != recorded, pool)
+}
+
+/// Check the units registered to the current context, reporting any orphan
+/// units and any references to missing units.
+///
+/// If `deny_dangling` is `true`, an error reporting all the dangling
+/// references is returned if any are found.
+pub fn check_units_in_context(conn: &sql::Connection, deny_dangling: bool) -> Result<()> {
+    let units = db::unit::get_all_in_context(conn)?;
     // Build the graph to check for orphan units
-    let units = db::unit::get_all_in_context(&conn)?;
     graph::of_units(&units);
-    Ok(())
+    let dangling: Vec<String> = check::dangling_refs(&units)
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+    if dangling.is_empty() {
+        Ok(())
+    } else if deny_dangling {
+        Err(Error::DanglingReferences(dangling).into())
+    } else {
+        dangling.iter().for_each(|d| log::warn!("{}", d));
+        Ok(())
+    }
 }
 
 // TODO Add support for setting default branch and upstream
-fn add(path: PathBuf) -> Result<()> {
-    let path = fs::canonicalize(path)?;
-    if !path.exists() {
-        Err(Error::RepoNotFound(path).into())
+fn add(
+    location: String,
+    rev: Option<String>,
+    deny_dangling: bool,
+    kinds: Vec<KindOverride>,
+    url_template: Option<UrlTemplate>,
+    query_upstream: bool,
+    jobs: usize,
+) -> Result<()> {
+    let mut repo = if repo::is_url(&location) {
+        Repo::new_remote(&location, query_upstream)?
     } else {
-        let conn = db::connection()?;
-        let repo = Repo::new_local(path)?;
-        match db::repo::add(&conn, &repo) {
-            // You'd think I could use a `map_err` here, but I can't for a
-            // reason I don't want to burn time unraveling at the moment.
-            Ok(()) => Ok(()),
-            Err(err) => {
-                if err
-                    .to_string()
-                    .contains("UNIQUE constraint failed: context_repo.context, context_repo.repo")
-                {
-                    Err(Error::RepoExists(repo.clone()).into())
-                } else {
-                    Err(err)
-                }
-            }
-        }?;
-        load_units_from_repo(&conn, &repo)
+        let path = fs::canonicalize(location)?;
+        if !path.exists() {
+            return Err(Error::RepoNotFound(path).into());
+        }
+        Repo::new_local(path, query_upstream)?
+    };
+    let mut conn = db::connection()?;
+    // Everything is added in a single transaction, so the context is left
+    // as it was if adding the repo fails at any point
+    let tx = conn.transaction()?;
+    repo.set_kind_overrides(kinds);
+    repo.set_url_template(url_template);
+    let pin = rev.map(|rev| repo.resolve(&rev)).transpose()?;
+    if let Some(pin) = &pin {
+        repo.load_config_at(pin)?;
     }
+    match db::repo::add(&tx, &repo) {
+        // You'd think I could use a `map_err` here, but I can't for a
+        // reason I don't want to burn time unraveling at the moment.
+        Ok(()) => Ok(()),
+        Err(err) => {
+            if err
+                .to_string()
+                .contains("UNIQUE constraint failed: context_repo.context, context_repo.repo")
+            {
+                Err(Error::RepoExists(Box::new(repo.clone())).into())
+            } else {
+                Err(err)
+            }
+        }
+    }?;
+    db::repo::set_pin(&tx, &repo, pin.as_ref())?;
+    let pool = jobs::pool(jobs)?;
+    let summary = Update::of_repo(&tx, &repo, pin.as_ref(), false, &pool)?.apply(&tx)?;
+    check_units_in_context(&tx, deny_dangling)?;
+    tx.commit()?;
+    log::info!("{}: {}", repo, summary);
+    Ok(())
+}
+
+fn remove(location: String, yes: bool) -> Result<()> {
+    // The repo may no longer exist on disk
+    let path = fs::canonicalize(&location).unwrap_or_else(|_| PathBuf::from(&location));
+    let mut conn = db::connection()?;
+    db::context::current(&conn)?.ok_or(db::Error::NoContext)?;
+    let repo = db::repo::get_all_in_context(&conn)?
+        .into_iter()
+        .find(|repo| {
+            repo.path_as_string() == location || (!repo.is_remote() && repo.path() == path)
+        })
+        .ok_or(Error::RepoNotInContext(location))?;
+    prompt::confirm(
+        &format!(
+            "Remove the repo {} and all of its units from the current context?",
+            repo
+        ),
+        yes,
+    )?;
+    let tx = conn.transaction()?;
+    let unused = db::repo::remove(&tx, &repo)?;
+    let remaining = db::repo::get_all(&tx)?;
+    tx.commit()?;
+    // Clones are only removed once the repo is forgotten by the db
+    Repo::remove_clones(&unused, &remaining)
 }
 
 pub fn run(opt: opt::Repo) -> Result<()> {
     match opt {
         opt::Repo::List {} => list(),
-        opt::Repo::Add { path } => add(path),
+        opt::Repo::Add {
+            repo,
+            rev,
+            deny_dangling,
+            kinds,
+            url_template,
+            query_upstream,
+            jobs,
+        } => add(
+            repo,
+            rev,
+            deny_dangling,
+            kinds,
+            url_template,
+            query_upstream,
+            jobs,
+        ),
+        opt::Repo::Remove { repo, yes } => remove(repo, yes),
     }
 }
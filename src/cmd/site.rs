@@ -1,7 +1,7 @@
 //! Generate HTML sites
 
 use {
-    crate::{db, graph, site::Html},
+    crate::{db, site},
     anyhow::Result,
 };
 
@@ -9,7 +9,6 @@ use {
 pub fn run() -> Result<()> {
     let conn = db::connection()?;
     let units = db::unit::get_all_in_context(&conn)?;
-    let graph = graph::of_units(&units);
-    print!("{}", Html::from(&graph));
+    print!("{}", site::of_units(&units));
     Ok(())
 }
@@ -3,13 +3,22 @@ use {
     anyhow::Result,
 };
 
-pub fn run() -> Result<()> {
-    let conn = db::connection()?;
-    let repos = db::repo::get_all_in_context(&conn)?;
-    for mut repo in repos {
-        db::repo::update(&conn, &mut repo)?;
-        db::unit::purge(&conn, &repo)?;
-        cmd::repo::load_units_from_repo(&conn, &repo)?;
+pub fn run(deny_dangling: bool, query_upstream: bool, jobs: usize) -> Result<()> {
+    let mut conn = db::connection()?;
+    let pool = cmd::jobs::pool(jobs)?;
+    // The context is synced in a single transaction, so it is left as it was
+    // if syncing any of its repos fails
+    let tx = conn.transaction()?;
+    let mut summaries = Vec::new();
+    for repo in db::repo::get_all_in_context(&tx)? {
+        let update = cmd::repo::sync_repo(&tx, repo.clone(), query_upstream, &pool)?;
+        summaries.push((repo, update.apply(&tx)?));
+    }
+    cmd::repo::check_units_in_context(&tx, deny_dangling)?;
+    tx.commit()?;
+
+    for (repo, summary) in summaries {
+        println!("{}: {}", repo, summary);
     }
     Ok(())
 }
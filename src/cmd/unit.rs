@@ -20,6 +20,8 @@ pub fn run(opt: opt::Unit) -> Result<()> {
     match opt {
         opt::Unit::List { format } => list(format),
         opt::Unit::Show { tag, format } => show(tag, format),
+        opt::Unit::Refs { tag, format } => refs(tag, format),
+        opt::Unit::Backrefs { tag, format } => backrefs(tag, format),
     }
 }
 
@@ -36,10 +38,34 @@ fn list(format: Option<Format>) -> Result<()> {
 
 fn show(tag: String, format: Option<Format>) -> Result<()> {
     let conn = db::connection()?;
-    let unit = db::unit::get(&conn, &tag)?.ok_or(Error::LogicalUnitNotFound(tag))?;
+    let units = db::unit::occurrences(&conn, &tag)?;
+    if units.is_empty() {
+        return Err(Error::LogicalUnitNotFound(tag).into());
+    }
+    match format {
+        None => show_human(units),
+        Some(fmt) => fmt.units(units),
+    }
+}
+
+fn refs(tag: String, format: Option<Format>) -> Result<()> {
+    let conn = db::connection()?;
+    if db::unit::get(&conn, &tag)?.is_none() {
+        return Err(Error::LogicalUnitNotFound(tag).into());
+    }
+    let units = db::unit::references(&conn, &tag)?;
     match format {
-        None => show_human(unit),
-        Some(fmt) => fmt.units(vec![unit]),
+        None => list_human(units),
+        Some(fmt) => fmt.units(units),
+    }
+}
+
+fn backrefs(tag: String, format: Option<Format>) -> Result<()> {
+    let conn = db::connection()?;
+    let units = db::unit::referrers(&conn, &tag)?;
+    match format {
+        None => list_human(units),
+        Some(fmt) => fmt.units(units),
     }
 }
 
@@ -54,17 +80,23 @@ fn list_human(units: Vec<LogicalUnit>) -> Result<()> {
     Ok(())
 }
 
-fn show_human(unit: LogicalUnit) -> Result<()> {
+// Shows the unit defining the tag, or else its first occurrence, followed by
+// the other `units` in which the tag occurs
+fn show_human(mut units: Vec<LogicalUnit>) -> Result<()> {
     let mut tw = TabWriter::new(stdout());
+    let unit = units.remove(0);
     let repo = unit.repo.clone().map_or("".into(), |r| r.path_as_string());
     let file: String = unit
         .file
         .map_or("".into(), |f| f.as_path().display().to_string());
     let line = unit.line.map_or("".into(), |l| l.to_string());
+    let span = unit
+        .span
+        .map_or("".into(), |(start, end)| format!("{}-{}", start, end));
     let refs = unit
         .references
         .iter()
-        .map(|id| id.to_string())
+        .map(|r| r.id.to_string())
         .collect::<Vec<String>>()
         .join(" ");
     let info = format!(
@@ -73,6 +105,7 @@ kind:\t{kind}
 repo:\t{repo}
 file:\t{file}
 line:\t{line}
+span:\t{span}
 refs:\t{refs}
 
 {content}
@@ -82,11 +115,25 @@ refs:\t{refs}
         repo = repo,
         file = file,
         line = line,
+        span = span,
         refs = refs,
         content = unit.content
     );
     tw.write_all(info.as_bytes())?;
 
+    if !units.is_empty() {
+        writeln!(&mut tw, "\noccurrences:")?;
+        for occurrence in units {
+            writeln!(
+                &mut tw,
+                "  {}\t{}\t{}",
+                occurrence.kind,
+                occurrence.location(),
+                occurrence.content.replace("\n", " ")
+            )?;
+        }
+    }
+
     let () = tw.flush()?;
     Ok(())
 }
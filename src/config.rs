@@ -0,0 +1,248 @@
+//! The configuration of a repo, checked in to a `.kontxt.toml` at its root.
+//!
+//! E.g.,
+//!
+//! ```toml
+//! # The files to parse, if not every supported file
+//! include = ["docs/**", "src/**"]
+//! # Files not to parse, even if included
+//! exclude = ["**/target/**", "vendor/**"]
+//! # The prefix of the tags of the units defined in the repo
+//! prefix = "FOO"
+//! # The template for links to the files of the repo
+//! url-template = "{repo}/src/{rev}/{path}#L{line}"
+//!
+//! # Files with these extensions are parsed like files with the given ones
+//! [extensions]
+//! mdx = "md"
+//!
+//! # The kind of the units found in files matching a glob. Where globs
+//! # overlap, the later kind takes precedence.
+//! [kinds]
+//! "specs/**/*.rs" = "model"
+//! ```
+//!
+//! Globs are matched against paths relative to the root of the repo.
+
+use {
+    crate::{
+        artifact::SourceFileKind,
+        hosting::UrlTemplate,
+        repo::{self, KindOverride},
+        util,
+    },
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        convert::TryFrom,
+        fs, io,
+        path::{Path, PathBuf},
+    },
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid configuration in {0}: {1}")]
+    Syntax(PathBuf, toml::de::Error),
+    #[error("invalid configuration in {0}: invalid glob {1}: {2}")]
+    Glob(PathBuf, String, glob::PatternError),
+    #[error("invalid configuration in {0}: files with extension {1} cannot be parsed like files with extension {2}")]
+    Extension(PathBuf, String, String),
+    #[error("invalid configuration in {0}: {1}")]
+    Kind(PathBuf, repo::Error),
+    #[error("invalid configuration in {0}: {1}")]
+    UrlTemplate(PathBuf, crate::hosting::Error),
+}
+
+/// The name of the file configuring a repo
+pub const FILE_NAME: &str = ".kontxt.toml";
+
+// The configuration, as written in the file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct File {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    extensions: BTreeMap<String, String>,
+    // Kept in the order of the file, since later kinds take precedence
+    kinds: toml::value::Table,
+    prefix: Option<String>,
+    url_template: Option<String>,
+}
+
+/// The configuration of a repo
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Config {
+    /// Globs matching the files to parse. All files are parsed if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Globs matching files not to parse
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Maps the extensions of files to the extension they are parsed as
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<KindOverride>,
+    /// The prefix of the tags of the units defined in the repo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_template: Option<UrlTemplate>,
+}
+
+impl Config {
+    /// `load(root)` is the configuration in the [`FILE_NAME`] at the `root`
+    /// of a repo, or else the default configuration
+    pub fn load(root: &Path) -> Result<Config> {
+        let path = root.join(FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Config::parse(&path, &contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// `parse(path, contents)` is the configuration with the `contents` of the
+    /// file at `path`
+    pub fn parse(path: &Path, contents: &str) -> Result<Config, Error> {
+        let file: File =
+            toml::from_str(contents).map_err(|e| Error::Syntax(path.to_path_buf(), e))?;
+        for glob in file.include.iter().chain(file.exclude.iter()) {
+            glob::Pattern::new(glob)
+                .map_err(|e| Error::Glob(path.to_path_buf(), glob.clone(), e))?;
+        }
+        for (ext, parsed_as) in file.extensions.iter() {
+            let supported =
+                SourceFileKind::try_from(Path::new("_").with_extension(parsed_as).as_path())
+                    .is_ok();
+            if !supported {
+                return Err(Error::Extension(
+                    path.to_path_buf(),
+                    ext.clone(),
+                    parsed_as.clone(),
+                ));
+            }
+        }
+        let kinds = file
+            .kinds
+            .iter()
+            .map(|(glob, kind)| {
+                let kind = kind
+                    .as_str()
+                    .map_or_else(|| kind.to_string(), str::to_string);
+                format!("{}={}", glob, kind).parse()
+            })
+            .collect::<Result<Vec<KindOverride>, repo::Error>>()
+            .map_err(|e| Error::Kind(path.to_path_buf(), e))?;
+        let url_template = file
+            .url_template
+            .map(|t| t.parse())
+            .transpose()
+            .map_err(|e| Error::UrlTemplate(path.to_path_buf(), e))?;
+        Ok(Config {
+            include: file.include,
+            exclude: file.exclude,
+            extensions: file.extensions,
+            kinds,
+            prefix: file.prefix,
+            url_template,
+        })
+    }
+
+    /// Whether the file at `path` is included among the files to parse
+    pub fn includes(&self, path: &Path) -> bool {
+        let matches = |globs: &[String]| globs.iter().any(|g| util::glob_matches(g, path));
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+
+    /// The path that the file at `path` is parsed as, with its extension
+    /// mapped according to the `extensions`
+    pub fn parsed_as(&self, path: &Path) -> PathBuf {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.extensions.get(ext))
+            .map_or_else(|| path.to_path_buf(), |ext| path.with_extension(ext))
+    }
+
+    /// Whether the configuration is the default one
+    pub fn is_default(&self) -> bool {
+        *self == Config::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::logical_unit::Kind};
+
+    fn parse(contents: &str) -> Result<Config, Error> {
+        Config::parse(Path::new(FILE_NAME), contents)
+    }
+
+    #[test]
+    fn can_parse_configuration() {
+        let config = parse(
+            r#"
+            include = ["docs/**", "src/**"]
+            exclude = ["**/target/**"]
+            prefix = "FOO"
+            url-template = "{repo}/src/{rev}/{path}#L{line}"
+
+            [extensions]
+            mdx = "md"
+
+            [kinds]
+            "docs/models/*.md" = "model"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.prefix.as_deref(), Some("FOO"));
+        assert_eq!(config.kinds[0].kind, Kind::Model);
+        assert!(config.url_template.is_some());
+
+        assert!(config.includes(Path::new("docs/spec.md")));
+        assert!(!config.includes(Path::new("README.md")));
+        assert!(!config.includes(Path::new("src/target/debug/spec.md")));
+
+        assert_eq!(
+            config.parsed_as(Path::new("docs/spec.mdx")),
+            PathBuf::from("docs/spec.md")
+        );
+        assert_eq!(
+            config.parsed_as(Path::new("src/lib.rs")),
+            PathBuf::from("src/lib.rs")
+        );
+    }
+
+    #[test]
+    fn keeps_kinds_in_order() {
+        let config = parse(
+            r#"
+            [kinds]
+            "specs/impl/*" = "implementation"
+            "specs/**" = "model"
+            "#,
+        )
+        .unwrap();
+        let kinds: Vec<Kind> = config.kinds.iter().map(|k| k.kind.clone()).collect();
+        assert_eq!(kinds, vec![Kind::Implementation, Kind::Model]);
+    }
+
+    #[test]
+    fn includes_every_file_by_default() {
+        let config = parse("").unwrap();
+        assert!(config.is_default());
+        assert!(config.includes(Path::new("target/spec.md")));
+    }
+
+    #[test]
+    fn rejects_invalid_configuration() {
+        assert!(parse("includes = []").is_err());
+        assert!(parse("exclude = [\"[\"]").is_err());
+        assert!(parse("[extensions]\nmdx = \"docx\"").is_err());
+        assert!(parse("[kinds]\n\"*.md\" = \"nonsense\"").is_err());
+        assert!(parse("url-template = \"{repo}\"").is_err());
+    }
+}
@@ -29,84 +29,67 @@ pub enum Error {
 
     #[error("Duplicate logical units found {0} {1}")]
     DuplicateUnits(String, String),
+
+    #[error(
+        "The db is at version {0}, but only versions up to {1} are known. Try updating kontxt"
+    )]
+    UnknownVersion(usize, usize),
 }
 
-fn create(conn: &sql::Connection, name: &str, statement: &str) -> Result<()> {
-    conn.prepare(statement)
-        .with_context(|| format!("init step: {}", name))?
-        .execute(sql::NO_PARAMS)
-        .map(|_| ()) // We don't care about the number of rows changed
-        .map_err(|e| e.into())
+/// A change to the schema of the db
+pub struct Migration {
+    /// A description of the change, used in reporting
+    pub name: &'static str,
+    statements: &'static str,
 }
 
-pub fn init(conn: &sql::Connection) -> Result<()> {
-    // Names of tables paired with the statement to the create the
-    // table. The names are used in error reporting.
-    // TODO Dedup name?
-    let statements = vec![
-        // Records the state of the app
-        (
-            "create appstate table",
-            r#"
+/// The migrations of the db schema, in the order they are applied
+///
+/// The version of the schema of a db is the number of migrations applied to
+/// it, which is recorded in its `user_version`. Released migrations must never
+/// be changed: the schema is changed by appending a new migration.
+pub const MIGRATIONS: [Migration; 4] = [
+    // The schema as it was before it was versioned. Every statement is
+    // idempotent, so unversioned dbs can be brought under versioning.
+    Migration {
+        name: "create the initial schema",
+        statements: r#"
+            -- Records the state of the app
             CREATE TABLE IF NOT EXISTS appstate (
                 id      INTEGER PRIMARY KEY CHECK (id = 1), -- Ensure there is only a single row
                 context INTEGER,
                 FOREIGN KEY(context) REFERENCES context(id)
             );
-            "#,
-        ),
-        // Records all contexts and their properties
-        (
-            "create context table",
-            r#"
+
+            -- Records all contexts and their properties
             CREATE TABLE IF NOT EXISTS context (
                 id   INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE
             );
-            "#,
-        ),
-        // Records all repos and their properties
-        (
-            "create repo table",
-            r#"
+
+            -- Records all repos and their properties
             CREATE TABLE IF NOT EXISTS repo (
                 id    INTEGER PRIMARY KEY,
                 path  TEXT NOT NULL UNIQUE, -- Path to repo (remote or local)
                 json  TEXT NOT NULL         -- A JSON serialization of the Repo struct
             );
-            "#,
-        ),
-        // Index repos by path, for quick lookup
-        (
-            "index repo table by path",
-            r#"
-            CREATE UNIQUE INDEX idx_repo_path
-            ON repo (path)
-            "#,
-        ),
-        // Records all logical units and their properties
-        (
-            "create units table",
-            r#"
+
+            -- Index repos by path, for quick lookup
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_repo_path
+            ON repo (path);
+
+            -- Records all logical units and their properties
             CREATE TABLE IF NOT EXISTS unit (
                 id      INTEGER PRIMARY KEY,
                 tag     TEXT NOT NULL UNIQUE,
                 json    TEXT NOT NULL         -- A JSON serialization of the LogicalUnit struct
             );
-            "#,
-        ),
-        // Index units by tag, for quick lookup
-        (
-            "index unit table by tag",
-            r#"
-            CREATE UNIQUE INDEX idx_unit_tag
-            ON unit (tag)
-            "#,
-        ),
-        // Records which repos are in which contexts
-        (
-            "create context_repo table",
-            r#"
+
+            -- Index units by tag, for quick lookup
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_unit_tag
+            ON unit (tag);
+
+            -- Records which repos are in which contexts
             CREATE TABLE IF NOT EXISTS context_repo (
                 id      INTEGER PRIMARY KEY,
                 context INTEGER NOT NULL,
@@ -115,12 +98,8 @@ pub fn init(conn: &sql::Connection) -> Result<()> {
                 FOREIGN KEY(repo) REFERENCES repo(id) ON DELETE CASCADE,
                 UNIQUE(context, repo)
             );
-            "#,
-        ),
-        // Records which units are in which repos
-        (
-            "create unit_repo table",
-            r#"
+
+            -- Records which units are in which repos
             CREATE TABLE IF NOT EXISTS unit_repo (
                 id      INTEGER PRIMARY KEY,
                 unit    INTEGER NOT NULL,
@@ -129,28 +108,194 @@ pub fn init(conn: &sql::Connection) -> Result<()> {
                 FOREIGN KEY(repo) REFERENCES repo(id) ON DELETE CASCADE,
                 UNIQUE(unit, repo)
             );
-            "#,
-        ),
-        // Initialize a blank state of the app
-        (
-            "insert empty appstate row",
-            r#"
-           INSERT INTO appstate (context)
-           VALUES (NULL)
-           "#,
-        ),
-    ];
-
-    statements
-        .iter()
-        .try_for_each(|(name, statement)| create(conn, name, statement))
+
+            -- Records which units are referenced by which units
+            --
+            -- The referenced unit is recorded by its tag, since references may
+            -- point to units that are not (yet) registered in the db.
+            CREATE TABLE IF NOT EXISTS unit_ref (
+                id      INTEGER PRIMARY KEY,
+                unit    INTEGER NOT NULL,
+                ref     TEXT NOT NULL,        -- The tag of the referenced unit
+                FOREIGN KEY(unit) REFERENCES unit(id) ON DELETE CASCADE,
+                UNIQUE(unit, ref)
+            );
+
+            -- Index unit references by the referenced tag, for quick lookup of
+            -- back-references
+            CREATE INDEX IF NOT EXISTS idx_unit_ref_ref
+            ON unit_ref (ref);
+
+            -- Initialize a blank state of the app
+            INSERT OR IGNORE INTO appstate (id, context)
+            VALUES (1, NULL);
+        "#,
+    },
+    // Units are recorded for each context and repo in which they are found,
+    // so contexts that include the same repo are kept separate, and a tag may
+    // occur in many units besides the one defining it.
+    Migration {
+        name: "record units per context, with many occurrences of each tag",
+        statements: r#"
+            CREATE TABLE unit_occurrence (
+                id          INTEGER PRIMARY KEY,
+                tag         TEXT NOT NULL,
+                json        TEXT NOT NULL,          -- A JSON serialization of the LogicalUnit struct
+                definition  INTEGER NOT NULL,       -- Whether the unit defines its tag, or just occurs
+                context     INTEGER NOT NULL,
+                repo        INTEGER NOT NULL,
+                FOREIGN KEY(context) REFERENCES context(id) ON DELETE CASCADE,
+                FOREIGN KEY(repo) REFERENCES repo(id) ON DELETE CASCADE
+            );
+
+            -- Each unit was only recorded once, so it is copied into every
+            -- context that includes its repo, once per context, even if more
+            -- than one of its repos are in the context. Units in no repo, or
+            -- whose repos are in no context, were never listed, and are
+            -- dropped.
+            CREATE TEMP TABLE unit_copy (
+                id      INTEGER PRIMARY KEY,    -- The id of the copy
+                unit    INTEGER NOT NULL,       -- The id of the copied unit
+                context INTEGER NOT NULL,
+                repo    INTEGER NOT NULL
+            );
+
+            INSERT INTO unit_copy (unit, context, repo)
+            SELECT unit_repo.unit, context_repo.context, MIN(unit_repo.repo)
+            FROM unit_repo
+            INNER JOIN context_repo ON context_repo.repo = unit_repo.repo
+            GROUP BY unit_repo.unit, context_repo.context;
+
+            INSERT INTO unit_occurrence (id, tag, json, definition, context, repo)
+            SELECT unit_copy.id,
+                   unit.tag,
+                   unit.json,
+                   json_extract(unit.json, '$.kind') = 'Requirement',
+                   unit_copy.context,
+                   unit_copy.repo
+            FROM unit_copy
+            INNER JOIN unit ON unit.id = unit_copy.unit;
+
+            -- Each copy of a unit makes the references of the unit
+            CREATE TEMP TABLE unit_ref_copy AS
+            SELECT unit_copy.id AS unit, unit_ref.ref AS ref
+            FROM unit_ref
+            INNER JOIN unit_copy ON unit_copy.unit = unit_ref.unit;
+
+            DELETE FROM unit_ref;
+            INSERT INTO unit_ref (unit, ref)
+            SELECT unit, ref FROM unit_ref_copy;
+
+            DROP TABLE unit_ref_copy;
+            DROP TABLE unit_copy;
+
+            DROP TABLE unit_repo;
+            DROP TABLE unit;
+            ALTER TABLE unit_occurrence RENAME TO unit;
+
+            -- Index units by context and tag, for quick lookup
+            CREATE INDEX idx_unit_tag
+            ON unit (context, tag);
+
+            -- Ensure each tag has at most one defining unit in a context, while
+            -- allowing any number of other occurrences
+            CREATE UNIQUE INDEX idx_unit_definition
+            ON unit (context, tag)
+            WHERE definition;
+        "#,
+    },
+    // Artifacts are recorded with the hashes of their contents, so only the
+    // artifacts that have changed need to be parsed again when syncing.
+    // Units recorded before this migration have no artifact, and are
+    // replaced on the next sync.
+    Migration {
+        name: "record the artifacts that units are found in",
+        statements: r#"
+            CREATE TABLE artifact (
+                id      INTEGER PRIMARY KEY,
+                context INTEGER NOT NULL,
+                repo    INTEGER NOT NULL,
+                path    TEXT NOT NULL,  -- The path to the artifact, relative to its repo
+                hash    TEXT NOT NULL,  -- The git blob id of the contents of the artifact
+                FOREIGN KEY(context) REFERENCES context(id) ON DELETE CASCADE,
+                FOREIGN KEY(repo) REFERENCES repo(id) ON DELETE CASCADE,
+                UNIQUE(context, repo, path)
+            );
+
+            ALTER TABLE unit
+            ADD COLUMN artifact INTEGER REFERENCES artifact(id) ON DELETE CASCADE;
+
+            -- Index units by artifact, for quick removal of the units of an
+            -- artifact
+            CREATE INDEX idx_unit_artifact
+            ON unit (artifact);
+        "#,
+    },
+    // A context can pin each of its repos to a revision, in which case the
+    // units of the repo are read from the tree of the pinned commit.
+    Migration {
+        name: "pin the repos of contexts to revisions",
+        statements: r#"
+            ALTER TABLE context_repo
+            ADD COLUMN rev TEXT;        -- The branch, tag, or commit the repo is pinned to
+
+            ALTER TABLE context_repo
+            ADD COLUMN commit_id TEXT;  -- The id of the commit the rev resolved to
+        "#,
+    },
+];
+
+/// `version(&conn)` is the version of the schema of the db
+pub fn version(conn: &sql::Connection) -> Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version", sql::NO_PARAMS, |row| row.get(0))?;
+    Ok(version as usize)
 }
 
-/// Open a connection to the app db
-pub fn connection() -> Result<sql::Connection> {
+/// `pending(&conn)` is the migrations that have not yet been applied to the db
+pub fn pending(conn: &sql::Connection) -> Result<&'static [Migration]> {
+    let version = version(conn)?;
+    MIGRATIONS
+        .get(version..)
+        .ok_or_else(|| Error::UnknownVersion(version, MIGRATIONS.len()).into())
+}
+
+/// `migrate(&conn)` applies all pending migrations to the db, each in its own
+/// transaction, returning the migrations that were applied
+///
+/// Foreign key constraints must not be enforced on the `conn`, since tables
+/// may be replaced during a migration.
+pub fn migrate(conn: &sql::Connection) -> Result<&'static [Migration]> {
+    let pending = pending(conn)?;
+    let applied = MIGRATIONS.len() - pending.len();
+    for (n, migration) in pending.iter().enumerate() {
+        let version = applied + n + 1;
+        let statements = format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration.statements, version
+        );
+        conn.execute_batch(&statements)
+            .inspect_err(|_| {
+                let _ = conn.execute_batch("ROLLBACK");
+            })
+            .with_context(|| format!("migrating to version {}: {}", version, migration.name))?;
+        log::info!("migrated db to version {}: {}", version, migration.name);
+    }
+    Ok(pending)
+}
+
+/// Open a connection to the app db, without migrating it
+pub fn open() -> Result<sql::Connection> {
     let dir = locations::tracer_db()?;
     let db_loc = dir.to_str().ok_or(Error::DbPath)?;
     let conn = sql::Connection::open(db_loc)?;
+    Ok(conn)
+}
+
+/// Open a connection to the app db, migrating it to the latest version of the
+/// schema if needed
+pub fn connection() -> Result<sql::Connection> {
+    let conn = open()?;
+    migrate(&conn)?;
     // Enable foreign keys
     // https://sqlite.org/foreignkeys.html#fk_enable
     conn.execute("PRAGMA foreign_keys = ON", sql::NO_PARAMS)?;
@@ -192,7 +337,7 @@ pub mod context {
     /// `get(&conn, name)` is:
     ///
     /// - `Ok(Some(context))` if there is a `context` with the given `name` in
-    ///    the db
+    ///   the db
     /// - `Ok(None)` if there is not a context with the the given `name`
     /// - `Err(err)` if the query fails for some reason
     pub fn get(conn: &sql::Connection, name: &str) -> Result<Option<Context>> {
@@ -222,6 +367,42 @@ pub mod context {
         }
     }
 
+    /// `rename(&conn, old, new)` renames the context named `old` to `new`
+    pub fn rename(conn: &sql::Connection, old: &str, new: &str) -> Result<()> {
+        if get(conn, old)?.is_none() {
+            Err(Error::NonexistentContext(old.to_string()).into())
+        } else {
+            let mut stmt = conn.prepare("UPDATE context SET name = :new WHERE name = :old")?;
+            stmt.execute_named(&[(":old", &old), (":new", &new)])
+                .map_err(|e| Error::Query(e).into())
+                .map(|_| ())
+        }
+    }
+
+    /// `delete(&conn, name)` deletes the context with the given `name`, along
+    /// with all of the units recorded in it. Repos that are left without any
+    /// context are removed from the db, and returned.
+    ///
+    /// If the context is the current context, no context is current
+    /// afterwards.
+    pub fn delete(conn: &sql::Connection, name: &str) -> Result<Vec<crate::repo::Repo>> {
+        if get(conn, name)?.is_none() {
+            return Err(Error::NonexistentContext(name.to_string()).into());
+        }
+        let query = r#"
+            UPDATE appstate
+            SET context = NULL
+            WHERE context = (SELECT id FROM context WHERE name = :name)
+        "#;
+        conn.prepare(query)?
+            .execute_named(&[(":name", &name)])
+            .map_err(Error::Query)?;
+        conn.prepare("DELETE FROM context WHERE name = :name")?
+            .execute_named(&[(":name", &name)])
+            .map_err(Error::Query)?;
+        repo::purge_unused(conn)
+    }
+
     /// `current(conn)` is
     ///
     /// - `Ok(Some(context))`, where `context` is the current working context,
@@ -242,11 +423,16 @@ pub mod context {
 }
 
 pub mod repo {
-    use {super::*, crate::repo::Repo, serde_json};
+    use {
+        super::*,
+        crate::repo::{Pin, Repo},
+        serde_json,
+        sql::OptionalExtension,
+    };
 
     pub(super) fn of_row(row: &sql::Row) -> sql::Result<Repo> {
         let json: String = row.get(2)?;
-        serde_json::from_str(&*json)
+        serde_json::from_str(&json)
             // TODO I'm not sure how to get the right error type here at the moment...
             .map_err(|_| sql::Error::InvalidParameterName("TODO returning wrong error".into()))
     }
@@ -258,18 +444,59 @@ pub mod repo {
         Ok(row)
     }
 
-    // TODO Is this actually useful?
     /// `purge(&conn, &repo)` removes the `repo` from the db as well as all units
-    /// registered to that repo and all relations to that repo
+    /// and artifacts registered to that repo and all relations to that repo, in
+    /// every context
     pub fn purge(conn: &sql::Connection, repo: &Repo) -> Result<()> {
-        unit::purge(&conn, &repo)?;
-        let mut stmt = conn.prepare("DELETE FROM repo WHERE path = :path")?;
-        let _ = stmt.execute_named(&[(":path", &repo.path_as_string())])?;
+        let path = repo.path_as_string();
+        for table in ["unit", "artifact", "context_repo"].iter() {
+            let query = format!(
+                "DELETE FROM {} WHERE repo = (SELECT id FROM repo WHERE path = :path)",
+                table
+            );
+            conn.execute_named(&query, &[(":path", &path)])
+                .map_err(Error::Query)?;
+        }
+        conn.execute_named("DELETE FROM repo WHERE path = :path", &[(":path", &path)])
+            .map_err(Error::Query)?;
         Ok(())
     }
 
-    pub fn update(conn: &sql::Connection, repo: &mut Repo) -> Result<()> {
-        repo.update()?;
+    /// `purge_unused(&conn)` removes all the repos that are not registered to
+    /// any context from the db, returning the removed repos
+    pub fn purge_unused(conn: &sql::Connection) -> Result<Vec<Repo>> {
+        let unused = conn
+            .prepare("SELECT * FROM repo WHERE id NOT IN (SELECT repo FROM context_repo)")?
+            .query_map(sql::NO_PARAMS, of_row)
+            .map_err(Error::Query)?
+            .collect::<sql::Result<Vec<Repo>>>()?;
+        let query = r#"
+            DELETE FROM repo
+            WHERE id NOT IN (SELECT repo FROM context_repo)
+        "#;
+        conn.execute(query, sql::NO_PARAMS).map_err(Error::Query)?;
+        Ok(unused)
+    }
+
+    /// `remove(&conn, &repo)` removes the `repo` from the current context,
+    /// along with all of its units in the context. The repo is removed from
+    /// the db if no other context uses it, in which case it is returned.
+    pub fn remove(conn: &sql::Connection, repo: &Repo) -> Result<Vec<Repo>> {
+        unit::purge(conn, repo)?;
+        let query = r#"
+            DELETE FROM context_repo
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :path)
+        "#;
+        conn.prepare(query)?
+            .execute_named(&[(":path", &repo.path_as_string())])
+            .map_err(Error::Query)?;
+        purge_unused(conn)
+    }
+
+    /// `update(&conn, &repo)` records the `repo`, after it has been updated
+    /// (see [`Repo::update`])
+    pub fn update(conn: &sql::Connection, repo: &Repo) -> Result<()> {
         let encoded = serde_json::to_string(repo)?;
         let path = repo.path_as_string();
 
@@ -279,12 +506,17 @@ pub mod repo {
             .map(|_| ())
     }
 
+    // Records the `repo`, replacing the record of a repo already added at the
+    // same path, so the settings given when it is added again take effect
     fn insert(conn: &sql::Connection, repo: &Repo) -> Result<()> {
         let encoded = serde_json::to_string(repo)?;
         let path = repo.path_as_string();
 
-        let mut stmt =
-            conn.prepare("INSERT OR IGNORE INTO repo (path, json) VALUES (:path, :json)")?;
+        let query = r#"
+            INSERT INTO repo (path, json) VALUES (:path, :json)
+            ON CONFLICT (path) DO UPDATE SET json = excluded.json
+        "#;
+        let mut stmt = conn.prepare(query)?;
         stmt.execute_named(&[(":path", &path), (":json", &encoded)])
             .map_err(|e| Error::Query(e).into())
             .map(|_| ())
@@ -305,7 +537,7 @@ pub mod repo {
     /// `add(&conn, &repo)` adds the
     pub fn add(conn: &sql::Connection, repo: &Repo) -> Result<()> {
         // TODO Should be able to do in one query?
-        let current_ctx = context::current(&conn)?;
+        let current_ctx = context::current(conn)?;
 
         match current_ctx {
             None => Err(Error::NoContext.into()),
@@ -316,6 +548,57 @@ pub mod repo {
         }
     }
 
+    /// `pin(&conn, &repo)` is the revision the `repo` is pinned to in the
+    /// current context, if any
+    pub fn pin(conn: &sql::Connection, repo: &Repo) -> Result<Option<Pin>> {
+        let query = r#"
+            SELECT context_repo.rev, context_repo.commit_id
+            FROM context_repo
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE context_repo.context = appstate.context
+              AND context_repo.repo = (SELECT id FROM repo WHERE path = :path)
+              AND context_repo.rev IS NOT NULL
+            "#;
+        let mut stmt = conn.prepare(query)?;
+        stmt.query_row_named(&[(":path", &repo.path_as_string())], |row| {
+            Ok(Pin {
+                rev: row.get(0)?,
+                commit: row.get(1)?,
+            })
+        })
+        .optional()
+        .map_err(|e| Error::Query(e).into())
+    }
+
+    /// `set_pin(&conn, &repo, pin)` pins the `repo` to the revision `pin` in
+    /// the current context, or unpins it if `pin` is `None`
+    pub fn set_pin(conn: &sql::Connection, repo: &Repo, pin: Option<&Pin>) -> Result<()> {
+        let query = r#"
+            UPDATE context_repo
+            SET rev = :rev, commit_id = :commit
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :path)
+        "#;
+        let mut stmt = conn.prepare(query)?;
+        stmt.execute_named(&[
+            (":rev", &pin.map(|p| p.rev.clone())),
+            (":commit", &pin.map(|p| p.commit.clone())),
+            (":path", &repo.path_as_string()),
+        ])
+        .map_err(|e| Error::Query(e).into())
+        .map(|_| ())
+    }
+
+    /// `get_all(conn)` is all the repos in the db, in any context
+    pub fn get_all(conn: &sql::Connection) -> Result<Vec<Repo>> {
+        let mut stmt = conn.prepare("SELECT * FROM repo")?;
+        let repos = stmt
+            .query_map(sql::NO_PARAMS, of_row)
+            .map_err(Error::Query)?
+            .collect::<sql::Result<Vec<Repo>>>()?;
+        Ok(repos)
+    }
+
     /// `get_all_in_context(conn)` is
     ///
     /// - `Ok(repos)` where `repos` are all the repos registered to the current context
@@ -344,45 +627,104 @@ pub mod repo {
 }
 
 // interfaces to logical units in db
+//
+// Units are only ever looked up in the current context.
 pub mod unit {
     use {
         super::*,
-        crate::{logical_unit::LogicalUnit, repo::Repo},
+        crate::{
+            artifact::SourceFileKind,
+            hosting::{Anchor, Rev},
+            logical_unit::LogicalUnit,
+            repo::Repo,
+        },
         sql::OptionalExtension,
+        std::convert::TryFrom,
     };
 
     fn of_row(row: &sql::Row) -> sql::Result<LogicalUnit> {
         let json: String = row.get(2)?;
-        serde_json::from_str(&*json)
+        serde_json::from_str(&json)
             // TODO I'm not sure how to get the right error type here at the moment...
             .map_err(|_| sql::Error::InvalidParameterName("TODO returning wrong error".into()))
     }
 
+    fn query_units(
+        conn: &sql::Connection,
+        query: &str,
+        params: &[(&str, &dyn sql::ToSql)],
+        ctx: &str,
+    ) -> Result<Vec<LogicalUnit>> {
+        let mut stmt = conn.prepare(query)?;
+        let rows = stmt
+            .query_map_named(params, of_row)
+            .map_err(Error::Query)
+            .with_context(|| ctx.to_string())?;
+
+        let mut units = Vec::new();
+        for u in rows {
+            units.push(u?);
+        }
+        Ok(units)
+    }
+
     /// `get(&conn, tag)` is:
     ///
     /// - `Ok(Some(unit))` if there is a `unit` with the given `tag` in
-    ///    the db
+    ///   the current context, preferring the unit defining the tag to its
+    ///   other occurrences
     /// - `Ok(None)` if there is not a unit with the the given `tag`
     /// - `Err(err)` if the query fails for some reason
     pub fn get(conn: &sql::Connection, tag: &str) -> Result<Option<LogicalUnit>> {
-        let mut stmt = conn.prepare("SELECT * FROM unit WHERE tag = :tag")?;
+        let query = r#"
+            SELECT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE unit.context = appstate.context
+              AND unit.tag = :tag
+            ORDER BY unit.definition DESC, unit.id
+            LIMIT 1
+            "#;
+        let mut stmt = conn.prepare(query)?;
         stmt.query_row_named(&[(":tag", &tag)], of_row)
             .optional()
             .map_err(|e| Error::Query(e).into())
     }
 
+    /// `occurrences(&conn, tag)` is all the units with the given `tag` in the
+    /// current context, starting with the unit defining the tag, if there is
+    /// one
+    pub fn occurrences(conn: &sql::Connection, tag: &str) -> Result<Vec<LogicalUnit>> {
+        let query = r#"
+            SELECT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE unit.context = appstate.context
+              AND unit.tag = :tag
+            ORDER BY unit.definition DESC, unit.id
+            "#;
+        query_units(
+            conn,
+            query,
+            &[(":tag", &tag)],
+            "fetching occurrences of unit",
+        )
+    }
+
     /// `get_uri(&conn, &tag)` is the uri to the unit indicated by `tag`.
-    /// If a remote URL can be onbtained for the source repo, that is is used
-    /// as the base, otherwise it falls back to the local path of the repo.
+    /// If the source repo is hosted, this links to the unit on its host,
+    /// otherwise it falls back to a `file://` URL (see [`Repo::url_of_file`]).
     pub fn get_path(conn: &sql::Connection, tag: &str) -> Result<String> {
-        let unit = get(conn, &tag)?.ok_or_else(|| Error::UnitNotFound(tag.into()))?;
+        let unit = get(conn, tag)?.ok_or_else(|| Error::UnitNotFound(tag.into()))?;
 
         let q = r#"
             SELECT repo.id, repo.path, repo.json
             FROM repo
-            INNER JOIN unit ON unit.tag = :tag
-            INNER JOIN unit_repo ON unit_repo.unit = unit.id
-            WHERE repo.id = unit_repo.repo
+            INNER JOIN appstate ON appstate.id = 1
+            INNER JOIN unit ON unit.repo = repo.id
+            WHERE unit.context = appstate.context
+              AND unit.tag = :tag
+            ORDER BY unit.definition DESC, unit.id
             "#;
         let mut stmt = conn.prepare(q)?;
         let repo = stmt
@@ -391,91 +733,232 @@ pub mod unit {
             .map_err(|e: sql::Error| -> anyhow::Error { Error::Query(e).into() })?
             .ok_or_else(|| Error::RelatedRepoNotFound(tag.into()))?;
 
-        // Construct the URL to a file on github from its upstream URL and default branch
-        let branch = (&repo).get_branch().unwrap_or_else(|| "master".to_string());
-        let mut url = (&repo).get_url();
-        url.push_str("/blob/");
-        url.push_str(&branch);
-        url.push('/');
-        url.push_str(&unit.file_path_as_str().unwrap_or_else(|| "".to_string()));
-        url.push('#');
-        url.push_str(tag);
+        // Files are linked at the commit the repo is pinned to, or else its
+        // default branch. Units defined in markdown are anchored by their
+        // tags (see `linkify`), while others are found at their line.
+        let pin = repo::pin(conn, &repo)?;
+        let branch = repo.get_branch().unwrap_or_else(|| "master".to_string());
+        let rev = match &pin {
+            Some(pin) => Rev::Commit(&pin.commit),
+            None => Rev::Branch(&branch),
+        };
+        let in_markdown = unit.file.as_deref().is_some_and(|file| {
+            matches!(SourceFileKind::try_from(file), Ok(SourceFileKind::Markdown))
+        });
+        let anchor = match unit.line {
+            Some(line) if !in_markdown => Anchor::Line(line),
+            _ => Anchor::Id(tag),
+        };
+        let file = unit.file_path_as_str().unwrap_or_else(|| "".to_string());
+        let url = repo.url_of_file(&file, rev, anchor);
         Ok(url)
     }
 
-    // Insert a unit into the db. This should not be used outside of this module,
-    // since it does not enforce any of the expected invariants.
-    fn insert(conn: &sql::Connection, unit: &LogicalUnit) -> Result<()> {
+    // Insert a unit found in the `artifact` of the `repo` into the current
+    // context, returning the id of its row. This should not be used outside
+    // of this module, since it does not enforce any of the expected
+    // invariants.
+    fn insert(
+        conn: &sql::Connection,
+        repo: &Repo,
+        artifact: i64,
+        unit: &LogicalUnit,
+    ) -> Result<i64> {
         let encoded = serde_json::to_string(unit)?;
-        let mut stmt = conn.prepare("INSERT INTO unit (tag, json) VALUES (:tag, :json)")?;
-        stmt.execute_named(&[(":tag", &unit.id.to_string()), (":json", &encoded)])
-            .map_err(|e| Error::Query(e).into())
-            .map(|_| ())
-    }
-
-    fn relate_to_repo(conn: &sql::Connection, repo: &Repo, unit: &LogicalUnit) -> Result<()> {
         let query = r#"
-            INSERT INTO unit_repo (unit, repo)
-            VALUES ((SELECT id FROM unit WHERE tag = :tag),
-                    (SELECT id FROM repo WHERE path = :path))
+            INSERT INTO unit (tag, json, definition, context, repo, artifact)
+            VALUES (:tag, :json, :definition,
+                    (SELECT context FROM appstate WHERE id = 1),
+                    (SELECT id FROM repo WHERE path = :path),
+                    :artifact)
         "#;
         let mut stmt = conn.prepare(query)?;
         stmt.execute_named(&[
             (":tag", &unit.id.to_string()),
+            (":json", &encoded),
+            (":definition", &unit.is_definition()),
             (":path", &repo.path_as_string()),
+            (":artifact", &artifact),
         ])
-        .map_err(|e| Error::Query(e).into())
-        .map(|_| ())
+        .map_err(Error::Query)?;
+        Ok(conn.last_insert_rowid())
     }
 
-    /// Adds the `unit` to the current context, and associates it with `repo`.
+    fn relate_to_references(
+        conn: &sql::Connection,
+        unit_id: i64,
+        unit: &LogicalUnit,
+    ) -> Result<()> {
+        let query = r#"
+            INSERT OR IGNORE INTO unit_ref (unit, ref)
+            VALUES (:unit, :ref)
+        "#;
+        let mut stmt = conn.prepare(query)?;
+        unit.references.iter().try_for_each(|reference| {
+            stmt.execute_named(&[(":unit", &unit_id), (":ref", &reference.id.to_string())])
+                .map_err(|e| Error::Query(e).into())
+                .map(|_| ())
+        })
+    }
+
+    /// Adds the `unit` to the current context, and associates it with `repo`
+    /// and the `artifact` it is found in.
     ///
-    /// If a conflicting unit is already present in the context, a
-    /// `Error::DuplicateUnits` is returned.
-    pub fn add(conn: &sql::Connection, repo: &Repo, unit: &LogicalUnit) -> Result<()> {
-        if let Some(other_unit) = get(&conn, &unit.id.to_string())? {
-            Err(Error::DuplicateUnits(unit.to_string(), other_unit.to_string()).into())
-        } else {
-            insert(conn, unit)?;
-            relate_to_repo(conn, repo, unit)
+    /// If the `unit` defines its tag, and another unit defining the same tag
+    /// is already present in the context, a `Error::DuplicateUnits` is
+    /// returned. Any number of units may occur with the same tag otherwise.
+    pub fn add(
+        conn: &sql::Connection,
+        repo: &Repo,
+        artifact: i64,
+        unit: &LogicalUnit,
+    ) -> Result<()> {
+        context::current(conn)?.ok_or(Error::NoContext)?;
+        let other_definition = get(conn, &unit.id.to_string())?.filter(|u| u.is_definition());
+        match other_definition {
+            Some(other_unit) if unit.is_definition() => {
+                Err(Error::DuplicateUnits(unit.to_string(), other_unit.to_string()).into())
+            }
+            _ => {
+                let unit_id = insert(conn, repo, artifact, unit)?;
+                relate_to_references(conn, unit_id, unit)
+            }
         }
     }
 
-    pub fn get_all_in_context(conn: &sql::Connection) -> Result<Vec<LogicalUnit>> {
+    fn query_in_context(
+        conn: &sql::Connection,
+        query: &str,
+        tag: &str,
+        ctx: &str,
+    ) -> Result<Vec<LogicalUnit>> {
+        let mut units = query_units(conn, query, &[(":tag", &tag)], ctx)?;
+        units.sort();
+        Ok(units)
+    }
+
+    /// `references(&conn, tag)` is
+    ///
+    /// - `Ok(units)` where `units` are all the units in the current context
+    ///   which are referred to by the unit with the given `tag`
+    /// - `Err(err)` in the event of a query error
+    pub fn references(conn: &sql::Connection, tag: &str) -> Result<Vec<LogicalUnit>> {
         let query = r#"
-            SELECT *
+            SELECT DISTINCT unit.*
             FROM unit
             INNER JOIN appstate ON appstate.id = 1
-            INNER JOIN context_repo ON context_repo.context = appstate.context
-            INNER JOIN unit_repo ON unit_repo.repo = context_repo.repo
-            WHERE unit.id = unit_repo.unit
+            INNER JOIN unit_ref ON unit_ref.ref = unit.tag
+            INNER JOIN unit AS referrer ON referrer.id = unit_ref.unit
+            WHERE unit.context = appstate.context
+              AND referrer.context = appstate.context
+              AND referrer.tag = :tag
             "#;
-        let mut stmt = conn.prepare(query)?;
-        let rows = stmt
-            .query_map(sql::NO_PARAMS, of_row)
-            .map_err(Error::Query)
-            .context("fetching all units in current context")?;
+        query_in_context(conn, query, tag, "fetching references of unit")
+    }
 
-        let mut units = Vec::new();
-        for u in rows {
-            units.push(u?);
-        }
+    /// `referrers(&conn, tag)` is
+    ///
+    /// - `Ok(units)` where `units` are all the units in the current context
+    ///   which refer to the unit with the given `tag`
+    /// - `Err(err)` in the event of a query error
+    pub fn referrers(conn: &sql::Connection, tag: &str) -> Result<Vec<LogicalUnit>> {
+        let query = r#"
+            SELECT DISTINCT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            INNER JOIN unit_ref ON unit_ref.unit = unit.id
+            WHERE unit.context = appstate.context
+              AND unit_ref.ref = :tag
+            "#;
+        query_in_context(conn, query, tag, "fetching referrers of unit")
+    }
+
+    pub fn get_all_in_context(conn: &sql::Connection) -> Result<Vec<LogicalUnit>> {
+        let query = r#"
+            SELECT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE unit.context = appstate.context
+            "#;
+        let mut units = query_units(conn, query, &[], "fetching all units in current context")?;
         // TODO Replace with an ordered set
         units.sort();
 
         Ok(units)
     }
 
-    /// `purge(&conn, &repo)` purges all units registered to the `repo`
+    /// `of_artifact(&conn, &repo, path)` is all the units in the current
+    /// context found in the artifact at `path` in the `repo`
+    pub fn of_artifact(
+        conn: &sql::Connection,
+        repo: &Repo,
+        path: &Path,
+    ) -> Result<Vec<LogicalUnit>> {
+        let query = r#"
+            SELECT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            INNER JOIN artifact ON artifact.id = unit.artifact
+            WHERE unit.context = appstate.context
+              AND artifact.repo = (SELECT id FROM repo WHERE path = :repo)
+              AND artifact.path = :path
+            "#;
+        query_units(
+            conn,
+            query,
+            &[
+                (":repo", &repo.path_as_string()),
+                (":path", &path.to_string_lossy().to_string()),
+            ],
+            "fetching units of artifact",
+        )
+    }
+
+    /// `untracked(&conn, &repo)` is all the units of the `repo` in the
+    /// current context that were recorded without their artifacts
+    pub fn untracked(conn: &sql::Connection, repo: &Repo) -> Result<Vec<LogicalUnit>> {
+        let query = r#"
+            SELECT unit.*
+            FROM unit
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE unit.context = appstate.context
+              AND unit.repo = (SELECT id FROM repo WHERE path = :repo)
+              AND unit.artifact IS NULL
+            "#;
+        query_units(
+            conn,
+            query,
+            &[(":repo", &repo.path_as_string())],
+            "fetching untracked units of repo",
+        )
+    }
+
+    /// `purge_untracked(&conn, &repo)` purges all units of the `repo` in the
+    /// current context that were recorded without their artifacts
+    pub fn purge_untracked(conn: &sql::Connection, repo: &Repo) -> Result<()> {
+        let mut stmt = conn.prepare(
+            r#"
+            DELETE FROM unit
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :path)
+              AND artifact IS NULL
+            "#,
+        )?;
+
+        stmt.execute_named(&[(":path", &repo.path_as_string())])
+            .map_err(|e| Error::Query(e).into())
+            .map(|_| ())
+    }
+
+    /// `purge(&conn, &repo)` purges all units registered to the `repo` in the
+    /// current context
     pub fn purge(conn: &sql::Connection, repo: &Repo) -> Result<()> {
+        artifact::purge(conn, repo)?;
         let mut stmt = conn.prepare(
             r#"
             DELETE FROM unit
-            WHERE id IN (
-                SELECT unit_repo.unit FROM unit_repo
-                INNER JOIN repo ON repo.path = :path
-                WHERE unit_repo.repo = repo.id
-            )
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :path)
             "#,
         )?;
 
@@ -484,3 +967,298 @@ pub mod unit {
             .map(|_| ())
     }
 }
+
+// interfaces to the artifacts in which units are found, recorded with the
+// hashes of their contents
+pub mod artifact {
+    use {
+        super::*,
+        crate::repo::Repo,
+        std::{collections::BTreeMap, path::PathBuf},
+    };
+
+    /// `hashes(&conn, &repo)` maps the path of each artifact of the `repo`
+    /// recorded in the current context to the hash of its contents
+    pub fn hashes(conn: &sql::Connection, repo: &Repo) -> Result<BTreeMap<PathBuf, String>> {
+        let query = r#"
+            SELECT artifact.path, artifact.hash
+            FROM artifact
+            INNER JOIN appstate ON appstate.id = 1
+            WHERE artifact.context = appstate.context
+              AND artifact.repo = (SELECT id FROM repo WHERE path = :repo)
+            "#;
+        let mut stmt = conn.prepare(query)?;
+        let rows = stmt
+            .query_map_named(&[(":repo", &repo.path_as_string())], |row| {
+                let path: String = row.get(0)?;
+                Ok((PathBuf::from(path), row.get(1)?))
+            })
+            .map_err(Error::Query)
+            .context("fetching artifacts of repo")?;
+
+        let mut hashes = BTreeMap::new();
+        for r in rows {
+            let (path, hash) = r?;
+            hashes.insert(path, hash);
+        }
+        Ok(hashes)
+    }
+
+    /// `add(&conn, &repo, path, hash)` records the artifact at `path` in the
+    /// `repo`, with contents hashed to `hash`, returning the id of its row
+    pub fn add(conn: &sql::Connection, repo: &Repo, path: &Path, hash: &str) -> Result<i64> {
+        let query = r#"
+            INSERT INTO artifact (context, repo, path, hash)
+            VALUES ((SELECT context FROM appstate WHERE id = 1),
+                    (SELECT id FROM repo WHERE path = :repo),
+                    :path,
+                    :hash)
+        "#;
+        let mut stmt = conn.prepare(query)?;
+        stmt.execute_named(&[
+            (":repo", &repo.path_as_string()),
+            (":path", &path.to_string_lossy().to_string()),
+            (":hash", &hash),
+        ])
+        .map_err(Error::Query)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// `remove(&conn, &repo, path)` removes the artifact at `path` in the
+    /// `repo` from the current context, along with all of its units
+    pub fn remove(conn: &sql::Connection, repo: &Repo, path: &Path) -> Result<()> {
+        let query = r#"
+            DELETE FROM artifact
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :repo)
+              AND path = :path
+        "#;
+        let mut stmt = conn.prepare(query)?;
+        stmt.execute_named(&[
+            (":repo", &repo.path_as_string()),
+            (":path", &path.to_string_lossy().to_string()),
+        ])
+        .map_err(|e| Error::Query(e).into())
+        .map(|_| ())
+    }
+
+    /// `purge(&conn, &repo)` removes all the artifacts of the `repo` from the
+    /// current context, along with all of their units
+    pub fn purge(conn: &sql::Connection, repo: &Repo) -> Result<()> {
+        let query = r#"
+            DELETE FROM artifact
+            WHERE context = (SELECT context FROM appstate WHERE id = 1)
+              AND repo = (SELECT id FROM repo WHERE path = :repo)
+        "#;
+        let mut stmt = conn.prepare(query)?;
+        stmt.execute_named(&[(":repo", &repo.path_as_string())])
+            .map_err(|e| Error::Query(e).into())
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_migrate_unversioned_dbs() {
+        let conn = sql::Connection::open_in_memory().unwrap();
+        // An unversioned db, as created before migrations were introduced
+        conn.execute_batch(MIGRATIONS[0].statements).unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO context (id, name) VALUES (1, 'foo'), (2, 'bar');
+            INSERT INTO repo (id, path, json) VALUES (1, '/repo', '{}'), (2, '/other', '{}');
+            INSERT INTO context_repo (context, repo) VALUES (1, 1), (2, 1), (2, 2);
+            INSERT INTO unit (id, tag, json) VALUES
+                (1, 'FOO.1', '{"id": "FOO.1", "kind": "Requirement"}'),
+                (2, 'BAR.1', '{"id":"BAR.1","kind":"Implementation","content":"\"kind\":\"Requirement\""}'),
+                (3, 'BAZ.1', '{"id":"BAZ.1","kind":"Requirement"}');
+            INSERT INTO unit_repo (unit, repo) VALUES (1, 1), (1, 2), (2, 2);
+            INSERT INTO unit_ref (unit, ref) VALUES (1, 'BAR.1'), (3, 'FOO.1');
+            "#,
+        )
+        .unwrap();
+        assert_eq!(version(&conn).unwrap(), 0);
+
+        assert_eq!(migrate(&conn).unwrap().len(), MIGRATIONS.len());
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+        assert!(migrate(&conn).unwrap().is_empty());
+
+        // Each unit is kept once in every context of its repos, and units in
+        // no repo are dropped. Units recorded without their artifacts are
+        // replaced on the next sync.
+        let mut stmt = conn
+            .prepare(
+                "SELECT tag, definition, context, repo, artifact FROM unit ORDER BY context, tag",
+            )
+            .unwrap();
+        let units: Vec<(String, bool, i64, i64, Option<i64>)> = stmt
+            .query_map(sql::NO_PARAMS, |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<sql::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            units,
+            vec![
+                ("FOO.1".to_string(), true, 1, 1, None),
+                ("BAR.1".to_string(), false, 2, 2, None),
+                ("FOO.1".to_string(), true, 2, 1, None),
+            ]
+        );
+        // Each copy of a unit makes its references
+        let mut stmt = conn
+            .prepare(
+                "SELECT unit.context, unit_ref.ref FROM unit_ref
+                 INNER JOIN unit ON unit.id = unit_ref.unit
+                 ORDER BY unit.context",
+            )
+            .unwrap();
+        let refs: Vec<(i64, String)> = stmt
+            .query_map(sql::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<sql::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            refs,
+            vec![(1, "BAR.1".to_string()), (2, "BAR.1".to_string())]
+        );
+        let refs: i64 = conn
+            .query_row("SELECT COUNT(*) FROM unit_ref", sql::NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(refs, 2);
+    }
+
+    #[test]
+    fn forgets_repos_that_are_not_in_any_context() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let repo = crate::repo::Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+
+        let conn = sql::Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", sql::NO_PARAMS)
+            .unwrap();
+        let repos = || {
+            conn.query_row("SELECT COUNT(*) FROM repo", sql::NO_PARAMS, |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap()
+        };
+
+        for name in ["foo", "bar"].iter() {
+            context::add(&conn, Context::new(name.to_string())).unwrap();
+            context::set(&conn, name.to_string()).unwrap();
+            repo::add(&conn, &repo).unwrap();
+        }
+
+        // The repo is still used by the context foo
+        repo::remove(&conn, &repo).unwrap();
+        assert!(repo::get_all_in_context(&conn).unwrap().is_empty());
+        assert_eq!(repos(), 1);
+
+        context::rename(&conn, "foo", "baz").unwrap();
+        context::delete(&conn, "baz").unwrap();
+        assert_eq!(repos(), 0);
+        let current = context::current(&conn).unwrap().map(|ctx| ctx.name);
+        assert_eq!(current, Some("bar".to_string()));
+
+        context::delete(&conn, "bar").unwrap();
+        assert!(context::get_all(&conn).unwrap().is_empty());
+        assert!(context::current(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn purges_repos_from_every_context() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let repo = crate::repo::Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+
+        let conn = sql::Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        for name in ["foo", "bar"].iter() {
+            context::add(&conn, Context::new(name.to_string())).unwrap();
+            context::set(&conn, name.to_string()).unwrap();
+            repo::add(&conn, &repo).unwrap();
+        }
+        conn.execute_batch(
+            r#"
+            INSERT INTO unit (tag, json, definition, context, repo)
+            SELECT 'FOO.1', '{}', 1, context, repo FROM context_repo;
+            "#,
+        )
+        .unwrap();
+        let count = |table: &str| {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM {}", table),
+                sql::NO_PARAMS,
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("unit"), 2);
+
+        repo::purge(&conn, &repo).unwrap();
+        for table in ["unit", "context_repo", "repo"].iter() {
+            assert_eq!(count(table), 0, "{} is purged", table);
+        }
+    }
+
+    #[test]
+    fn updates_the_settings_of_repos_added_again() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let mut repo = crate::repo::Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+
+        let conn = sql::Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        context::add(&conn, Context::new("foo".to_string())).unwrap();
+        context::set(&conn, "foo".to_string()).unwrap();
+        repo::add(&conn, &repo).unwrap();
+
+        context::add(&conn, Context::new("bar".to_string())).unwrap();
+        context::set(&conn, "bar".to_string()).unwrap();
+        repo.set_kind_overrides(vec!["specs/*.md=model".parse().unwrap()]);
+        repo::add(&conn, &repo).unwrap();
+
+        let added = repo::get_all_in_context(&conn).unwrap().remove(0);
+        assert_eq!(
+            added.kind_override(Path::new("specs/spec.md")),
+            Some(crate::logical_unit::Kind::Model)
+        );
+    }
+
+    #[test]
+    fn updates_the_url_template_of_repos_added_again() {
+        use crate::hosting::{Anchor, Rev};
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let mut repo = crate::repo::Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+
+        let conn = sql::Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        for (name, template) in [("foo", "https://a/{path}"), ("bar", "https://b/{path}")].iter() {
+            context::add(&conn, Context::new(name.to_string())).unwrap();
+            context::set(&conn, name.to_string()).unwrap();
+            repo.set_url_template(Some(template.parse().unwrap()));
+            repo::add(&conn, &repo).unwrap();
+        }
+
+        let added = repo::get_all_in_context(&conn).unwrap().remove(0);
+        assert_eq!(
+            added.url_of_file("spec.md", Rev::Branch("main"), Anchor::Line(1)),
+            "https://b/spec.md"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use {
-    crate::logical_unit::LogicalUnit,
+    crate::logical_unit::{self, LogicalUnit},
     log,
     petgraph::{
         dot::{Config, Dot},
@@ -33,11 +33,15 @@ pub fn of_units(units: &[LogicalUnit]) -> UnitGraph {
     log::debug!("generating graph of units");
     let mut graph: UnitGraph = StableGraph::new();
     // map from unit id to the unit and its index in the graph (if it's been added)
-    let mut map = BTreeMap::new();
-    for u in units {
-        map.insert(u.id.to_string(), (u, None));
+    // Each tag is represented by its definition, or else its first occurrence
+    let mut map: UnitMap = BTreeMap::new();
+    for (id, occurrences) in logical_unit::occurrences(units) {
+        map.insert(id.to_string(), (occurrences[0], None));
     }
     for u in units {
+        if !std::ptr::eq(map[&u.id.to_string()].0, u) {
+            continue;
+        }
         // If we're adding the unit fresh to the graph
         if let (true, idx) = try_insert_node(&mut graph, &mut map, u) {
             if let Some(parent_id) = u.parent_id() {
@@ -0,0 +1,293 @@
+//! Links to the files of repos on code hosting services.
+//!
+//! The upstream URL of a repo is parsed into a [`Remote`], giving the web
+//! address of the repo, and the URLs of its files are built from a
+//! [`UrlTemplate`]. The template is chosen by the kind of [`Host`] serving the
+//! repo, unless it is overridden for the repo.
+
+use {
+    serde::{Deserialize, Serialize},
+    std::{fmt, str::FromStr},
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid URL template {0}: unknown placeholder {{{1}}}")]
+    UnknownPlaceholder(String, String),
+    #[error("invalid URL template {0}: missing the {{path}} placeholder")]
+    MissingPath(String),
+}
+
+/// The code hosting services with known URL layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Host {
+    GitHub,
+    GitLab,
+    Gitea,
+    Bitbucket,
+}
+
+impl Host {
+    /// The kind of host serving the `domain`, if it can be recognized. Self
+    /// hosted instances are recognized when they are named after the service
+    /// they run, e.g., `gitlab.example.com`.
+    pub fn of_domain(domain: &str) -> Option<Host> {
+        let domain = domain.to_lowercase();
+        let named = |names: &[&str]| names.iter().any(|name| domain.contains(name));
+        if named(&["github"]) {
+            Some(Host::GitHub)
+        } else if named(&["gitlab"]) {
+            Some(Host::GitLab)
+        } else if named(&["gitea", "codeberg", "forgejo"]) {
+            Some(Host::Gitea)
+        } else if named(&["bitbucket"]) {
+            Some(Host::Bitbucket)
+        } else {
+            None
+        }
+    }
+
+    /// The template for the URLs of files on the host
+    pub fn template(self) -> UrlTemplate {
+        let template = match self {
+            Host::GitHub => "{repo}/blob/{rev}/{path}#L{line}",
+            Host::GitLab => "{repo}/-/blob/{rev}/{path}#L{line}",
+            Host::Gitea => "{repo}/src/{rev_kind}/{rev}/{path}#L{line}",
+            Host::Bitbucket => "{repo}/src/{rev}/{path}#lines-{line}",
+        };
+        UrlTemplate(template.to_string())
+    }
+}
+
+/// A repo on a host, as addressed by its upstream URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// The scheme, domain, and (optionally) port of the host's web interface
+    pub origin: String,
+    /// The domain of the host
+    pub domain: String,
+    /// The path of the repo on the host, e.g., `informalsystems/themis-tracer`
+    pub path: String,
+}
+
+impl Remote {
+    /// `parse(url)` is the remote addressed by the `url`, if it is an SSH URL,
+    /// either as `ssh://[user@]host[:port]/path` or in the scp-like syntax
+    /// `[user@]host:path`, or an HTTP(S) URL.
+    ///
+    /// The web interface of a host isn't served over SSH, so the port of an
+    /// SSH URL is dropped, while the port of an HTTP(S) URL is kept.
+    pub fn parse(url: &str) -> Option<Remote> {
+        let (scheme, authority, path) = match url.split_once("://") {
+            Some((scheme, rest)) => {
+                let (authority, path) = rest.split_once('/')?;
+                (scheme, authority, path)
+            }
+            None => {
+                let (authority, path) = url.split_once(':')?;
+                if authority.contains('/') {
+                    return None;
+                }
+                ("ssh", authority, path)
+            }
+        };
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let (origin, domain) = match scheme {
+            "http" | "https" => (format!("{}://{}", scheme, host), domain_of(host)),
+            "ssh" | "git+ssh" | "git" => {
+                let domain = domain_of(host);
+                (format!("https://{}", domain), domain)
+            }
+            _ => return None,
+        };
+        let path = path.trim_matches('/');
+        let path = path.strip_suffix(".git").unwrap_or(path);
+        if domain.is_empty() || path.is_empty() {
+            return None;
+        }
+        Some(Remote {
+            origin,
+            domain: domain.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// The kind of host serving the repo, if it can be recognized
+    pub fn host(&self) -> Option<Host> {
+        Host::of_domain(&self.domain)
+    }
+}
+
+fn domain_of(host: &str) -> &str {
+    host.split_once(':').map_or(host, |(domain, _)| domain)
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.origin, self.path)
+    }
+}
+
+/// The revision of a repo that a file is linked at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rev<'a> {
+    Branch(&'a str),
+    Commit(&'a str),
+}
+
+/// What a link to a file points to within the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor<'a> {
+    /// A line of the file
+    Line(u64),
+    /// An element of the rendered file, with the given id
+    Id(&'a str),
+}
+
+const PLACEHOLDERS: &[&str] = &["repo", "rev", "rev_kind", "path", "line"];
+
+/// A template for the URLs of the files in a repo, with the placeholders
+///
+/// - `{repo}`: the web address of the repo
+/// - `{rev}`: the branch or commit that the file is linked at
+/// - `{rev_kind}`: either `branch` or `commit`, according to the `{rev}`
+/// - `{path}`: the path of the file, relative to the root of the repo
+/// - `{line}`: the line linked to
+///
+/// The fragment of the template (following the `#`) anchors a line, and is
+/// replaced by the id when linking to an element of a rendered file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct UrlTemplate(String);
+
+impl UrlTemplate {
+    /// The URL of the file at `path` in the `repo`, at the `rev`
+    pub fn url(&self, repo: &str, rev: Rev, path: &str, anchor: Anchor) -> String {
+        let (rev, rev_kind) = match rev {
+            Rev::Branch(branch) => (branch, "branch"),
+            Rev::Commit(commit) => (commit, "commit"),
+        };
+        let (file, fragment) = self.0.split_once('#').unwrap_or((&self.0, ""));
+        let file = file
+            .replace("{repo}", repo)
+            .replace("{rev_kind}", rev_kind)
+            .replace("{rev}", rev)
+            .replace("{path}", path);
+        match anchor {
+            Anchor::Line(line) if !fragment.is_empty() => {
+                format!("{}#{}", file, fragment.replace("{line}", &line.to_string()))
+            }
+            Anchor::Line(_) => file,
+            Anchor::Id(id) => format!("{}#{}", file, id),
+        }
+    }
+}
+
+impl FromStr for UrlTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some((_, after)) = rest.split_once('{') {
+            let (name, after) = after.split_once('}').unwrap_or((after, ""));
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(Error::UnknownPlaceholder(s.to_string(), name.to_string()));
+            }
+            rest = after;
+        }
+        if !s.contains("{path}") {
+            return Err(Error::MissingPath(s.to_string()));
+        }
+        Ok(UrlTemplate(s.to_string()))
+    }
+}
+
+impl fmt::Display for UrlTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn remote(url: &str) -> String {
+        Remote::parse(url).unwrap().to_string()
+    }
+
+    #[test]
+    fn can_parse_remotes_from_upstream_urls() {
+        let expected = "https://github.com/informalsystems/themis-tracer";
+        assert_eq!(
+            remote("git@github.com:informalsystems/themis-tracer.git"),
+            expected
+        );
+        assert_eq!(
+            remote("ssh://git@github.com:22/informalsystems/themis-tracer.git"),
+            expected
+        );
+        assert_eq!(
+            remote("https://user@github.com/informalsystems/themis-tracer/"),
+            expected
+        );
+        assert_eq!(
+            remote("http://gitea.example.com:3000/org/repo.git"),
+            "http://gitea.example.com:3000/org/repo"
+        );
+        assert!(Remote::parse("file:///repos/remote.git").is_none());
+        assert!(Remote::parse("/repos/remote").is_none());
+        assert!(Remote::parse("repos/a:b").is_none());
+    }
+
+    #[test]
+    fn can_recognize_hosts() {
+        let host = |url| Remote::parse(url).unwrap().host();
+        assert_eq!(host("git@github.com:o/r.git"), Some(Host::GitHub));
+        assert_eq!(host("https://gitlab.example.com/g/r"), Some(Host::GitLab));
+        assert_eq!(host("https://codeberg.org/o/r"), Some(Host::Gitea));
+        assert_eq!(host("git@bitbucket.org:o/r.git"), Some(Host::Bitbucket));
+        assert_eq!(host("https://git.example.com/o/r"), None);
+    }
+
+    #[test]
+    fn can_build_urls_for_each_host() {
+        let url = |host: Host, rev, anchor| {
+            host.template()
+                .url("https://h/o/r", rev, "dir/spec.md", anchor)
+        };
+        assert_eq!(
+            url(Host::GitHub, Rev::Branch("main"), Anchor::Id("FOO.1")),
+            "https://h/o/r/blob/main/dir/spec.md#FOO.1"
+        );
+        assert_eq!(
+            url(Host::GitLab, Rev::Commit("abc"), Anchor::Line(3)),
+            "https://h/o/r/-/blob/abc/dir/spec.md#L3"
+        );
+        assert_eq!(
+            url(Host::Gitea, Rev::Branch("main"), Anchor::Line(3)),
+            "https://h/o/r/src/branch/main/dir/spec.md#L3"
+        );
+        assert_eq!(
+            url(Host::Gitea, Rev::Commit("abc"), Anchor::Line(3)),
+            "https://h/o/r/src/commit/abc/dir/spec.md#L3"
+        );
+        assert_eq!(
+            url(Host::Bitbucket, Rev::Commit("abc"), Anchor::Line(3)),
+            "https://h/o/r/src/abc/dir/spec.md#lines-3"
+        );
+    }
+
+    #[test]
+    fn can_parse_url_templates() {
+        let template: UrlTemplate = "https://cgit.example.com/{path}?id={rev}".parse().unwrap();
+        assert_eq!(
+            template.url("", Rev::Commit("abc"), "spec.md", Anchor::Line(3)),
+            "https://cgit.example.com/spec.md?id=abc"
+        );
+        assert!("{repo}/{rev}".parse::<UrlTemplate>().is_err());
+        assert!("{repo}/{branch}/{path}".parse::<UrlTemplate>().is_err());
+    }
+}
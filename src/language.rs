@@ -0,0 +1,634 @@
+//!
+//! The programming languages in which logical units can be tagged
+//!
+//! Each language is described by its comment syntax, so that tags are only
+//! found in comments. Support for a new language is added by describing it in
+//! [LANGUAGES].
+//!
+
+use {lazy_static::lazy_static, regex::Regex, std::path::Path};
+
+lazy_static! {
+    /// Matches attributes tagging the logical units implemented by an item,
+    /// like `#[implements("FOO.1", "FOO.1::BAR.1")]`, capturing the arguments
+    static ref RUST_IMPLEMENTS_RE: Regex =
+        Regex::new(r#"#\[\s*(?:[A-Za-z_][A-Za-z0-9_]*::)*implements\s*\((?P<args>[^\]]*)\)\s*\]"#).unwrap();
+
+    /// Matches a string literal, capturing its content
+    static ref STRING_LITERAL_RE: Regex = Regex::new(r#""(?P<content>[^"]*)""#).unwrap();
+
+    /// Matches the start of the definition of a rust item, like `pub fn foo`,
+    /// capturing the kind of the item and the rest of the definition
+    static ref RUST_ITEM_RE: Regex = Regex::new(
+        r#"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:const|async|unsafe|default|extern(?:\s+"[^"]*")?)\s+)*(?P<kind>fn|struct|enum|union|trait|type|mod|const|static|impl|macro_rules!)(?P<rest>[\s<].*)$"#
+    )
+    .unwrap();
+
+    /// Matches the attributes of code which is only compiled for tests, like
+    /// `#[cfg(test)]` or `#[cfg(all(test, unix))]`
+    static ref RUST_CFG_TEST_RE: Regex =
+        Regex::new(r"^#\[cfg\((all\(([^()]*,\s*)?)?test\b").unwrap();
+
+    /// Matches the opening of a raw string literal, like `r#"`, capturing the
+    /// hashes which must also close it
+    static ref RUST_RAW_STRING_RE: Regex = Regex::new(r##"^b?r(?P<hashes>#*)""##).unwrap();
+
+    /// Matches the start of the definition of a python function or class
+    static ref PYTHON_DEF_RE: Regex = Regex::new(r"^\s*(async\s+def|def|class)\b").unwrap();
+
+    /// Matches an identifier at the start of a string
+    static ref IDENT_RE: Regex = Regex::new(r"^\s*(?P<ident>[A-Za-z_][A-Za-z0-9_]*)").unwrap();
+}
+
+/// The comment syntax of a programming language, and how to recognize its tests
+#[derive(Debug)]
+pub struct Language {
+    pub name: &'static str,
+    /// The extensions of source files in the language
+    pub extensions: &'static [&'static str],
+    /// Markers which start a comment running to the end of the line
+    pub line_comments: &'static [&'static str],
+    /// Pairs of markers opening and closing a comment
+    pub block_comments: &'static [(&'static str, &'static str)],
+    /// Characters which delimit string literals
+    pub string_delimiters: &'static [char],
+    /// Markers delimiting docstrings, which are comments when they are the
+    /// first statement of a module, class, or function, and strings otherwise
+    pub docstrings: &'static [&'static str],
+    /// Whether there are raw string literals, like `r#"..."#`
+    pub raw_strings: bool,
+    /// Globs matching the names of files containing tests
+    pub test_files: &'static [&'static str],
+    /// Finds which lines of a source file belong to test code
+    pub test_lines: fn(&[String]) -> Vec<bool>,
+    /// Finds the tags given in attributes in the code of a line
+    pub tagged_attributes: fn(&str) -> Vec<String>,
+    /// Describes the item defined in the code of a line, if any
+    pub item_of_line: fn(&str) -> Option<String>,
+}
+
+const C_LINE_COMMENTS: &[&str] = &["//"];
+const C_BLOCK_COMMENTS: &[(&str, &str)] = &[("/*", "*/")];
+
+pub static RUST: Language = Language {
+    name: "Rust",
+    extensions: &["rs"],
+    line_comments: C_LINE_COMMENTS,
+    block_comments: C_BLOCK_COMMENTS,
+    // Single quotes are left out, since they also mark lifetimes
+    string_delimiters: &['"'],
+    docstrings: &[],
+    raw_strings: true,
+    test_files: &[],
+    test_lines: rust_test_lines,
+    tagged_attributes: rust_tagged_attributes,
+    item_of_line: rust_item_of_line,
+};
+
+pub static GO: Language = Language {
+    name: "Go",
+    extensions: &["go"],
+    line_comments: C_LINE_COMMENTS,
+    block_comments: C_BLOCK_COMMENTS,
+    string_delimiters: &['"', '\'', '`'],
+    docstrings: &[],
+    raw_strings: false,
+    test_files: &["*_test.go"],
+    test_lines: no_test_lines,
+    tagged_attributes: no_tagged_attributes,
+    item_of_line: no_item_of_line,
+};
+
+pub static PYTHON: Language = Language {
+    name: "Python",
+    extensions: &["py"],
+    line_comments: &["#"],
+    block_comments: &[],
+    string_delimiters: &['"', '\''],
+    docstrings: &["\"\"\"", "'''"],
+    raw_strings: false,
+    test_files: &["test_*.py", "*_test.py"],
+    test_lines: no_test_lines,
+    tagged_attributes: no_tagged_attributes,
+    item_of_line: no_item_of_line,
+};
+
+pub static TYPESCRIPT: Language = Language {
+    name: "TypeScript",
+    extensions: &["ts", "tsx", "js", "jsx", "mjs", "cjs"],
+    line_comments: C_LINE_COMMENTS,
+    block_comments: C_BLOCK_COMMENTS,
+    string_delimiters: &['"', '\'', '`'],
+    docstrings: &[],
+    raw_strings: false,
+    test_files: &["*.test.*", "*.spec.*"],
+    test_lines: no_test_lines,
+    tagged_attributes: no_tagged_attributes,
+    item_of_line: no_item_of_line,
+};
+
+pub static C: Language = Language {
+    name: "C",
+    extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx"],
+    line_comments: C_LINE_COMMENTS,
+    block_comments: C_BLOCK_COMMENTS,
+    string_delimiters: &['"', '\''],
+    docstrings: &[],
+    raw_strings: false,
+    test_files: &[],
+    test_lines: no_test_lines,
+    tagged_attributes: no_tagged_attributes,
+    item_of_line: no_item_of_line,
+};
+
+/// All the supported programming languages
+pub static LANGUAGES: [&Language; 5] = [&RUST, &GO, &PYTHON, &TYPESCRIPT, &C];
+
+/// A line of source code, split into code and comments
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceLine {
+    /// The code on the line, including any string literals
+    pub code: String,
+    /// The text of the comments on the line, without the comment markers
+    pub comment: Option<String>,
+}
+
+impl SourceLine {
+    /// Whether there is nothing but comments on the line
+    pub fn is_comment_only(&self) -> bool {
+        self.comment.is_some() && self.code.trim().is_empty()
+    }
+}
+
+impl Language {
+    /// The language of source files with the extension `ext`, if it is supported
+    pub fn of_extension(ext: &str) -> Option<&'static Language> {
+        LANGUAGES
+            .iter()
+            .find(|lang| lang.extensions.contains(&ext))
+            .copied()
+    }
+
+    /// Whether the file at `path` contains tests, judging by its name
+    pub fn is_test_file(&self, path: &Path) -> bool {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return false,
+        };
+        self.test_files
+            .iter()
+            .filter_map(|glob| glob::Pattern::new(glob).ok())
+            .any(|pattern| pattern.matches(name))
+    }
+
+    /// Each of the `lines` of source code, split into code and comments
+    pub fn split_comments(&self, lines: &[String]) -> Vec<SourceLine> {
+        // The block comment or string literal we are in, if any
+        let mut open: Option<Open> = None;
+        // Whether a docstring may start on the next line, which it does at the
+        // start of the file, and of the body of a function or class
+        let mut expect_docstring = true;
+        // Whether we are in the header of a function or class, before its body
+        let mut in_header = false;
+        lines
+            .iter()
+            .map(|line| {
+                let mut code = String::new();
+                let mut comment: Option<String> = None;
+                let mut has_docstring = false;
+                let mut rest = line.as_str();
+                while !rest.is_empty() {
+                    match &open {
+                        Some(Open::Comment(close)) => {
+                            let text = comment.get_or_insert_with(String::new);
+                            match rest.find(close) {
+                                Some(i) => {
+                                    text.push_str(&rest[..i]);
+                                    rest = &rest[i + close.len()..];
+                                    open = None;
+                                }
+                                None => {
+                                    text.push_str(rest);
+                                    rest = "";
+                                }
+                            }
+                        }
+                        Some(Open::String(close)) => match rest.find(close.as_str()) {
+                            Some(i) => {
+                                let len = i + close.len();
+                                code.push_str(&rest[..len]);
+                                rest = &rest[len..];
+                                open = None;
+                            }
+                            None => {
+                                code.push_str(rest);
+                                rest = "";
+                            }
+                        },
+                        None => {
+                            let prev = code.chars().last();
+                            if let Some(&(start, close)) = self
+                                .block_comments
+                                .iter()
+                                .find(|(o, _)| rest.starts_with(o))
+                            {
+                                comment.get_or_insert_with(String::new);
+                                rest = &rest[start.len()..];
+                                open = Some(Open::Comment(close));
+                            } else if let Some(&quote) =
+                                self.docstrings.iter().find(|q| rest.starts_with(*q))
+                            {
+                                rest = &rest[quote.len()..];
+                                let is_first_statement = if code.trim().is_empty() {
+                                    expect_docstring
+                                } else {
+                                    PYTHON_DEF_RE.is_match(&code) && code.trim_end().ends_with(':')
+                                };
+                                if is_first_statement {
+                                    comment.get_or_insert_with(String::new);
+                                    open = Some(Open::Comment(quote));
+                                    has_docstring = true;
+                                } else {
+                                    code.push_str(quote);
+                                    open = Some(Open::String(quote.to_string()));
+                                }
+                            } else if let Some(marker) =
+                                self.line_comments.iter().find(|m| rest.starts_with(*m))
+                            {
+                                comment
+                                    .get_or_insert_with(String::new)
+                                    .push_str(&rest[marker.len()..]);
+                                rest = "";
+                            } else if let Some((len, close)) =
+                                raw_string_markers(rest).filter(|_| {
+                                    self.raw_strings && prev.is_none_or(|c| !is_ident_char(c))
+                                })
+                            {
+                                code.push_str(&rest[..len]);
+                                rest = &rest[len..];
+                                open = Some(Open::String(close));
+                            } else {
+                                let c = rest.chars().next().unwrap_or_default();
+                                let len = if self.string_delimiters.contains(&c) {
+                                    string_literal_len(rest, c)
+                                } else {
+                                    c.len_utf8()
+                                };
+                                code.push_str(&rest[..len]);
+                                rest = &rest[len..];
+                            }
+                        }
+                    }
+                }
+                let statement = code.trim();
+                if has_docstring {
+                    expect_docstring = false;
+                    in_header = false;
+                } else if !statement.is_empty() && !self.docstrings.is_empty() {
+                    in_header |= PYTHON_DEF_RE.is_match(statement);
+                    expect_docstring = in_header && statement.ends_with(':');
+                    in_header &= !expect_docstring;
+                }
+                SourceLine {
+                    code,
+                    comment: comment.map(|c| c.trim_end().to_string()),
+                }
+            })
+            .collect()
+    }
+}
+
+// A block comment or string literal left open at the end of a line
+enum Open {
+    // A comment, closed by the given marker
+    Comment(&'static str),
+    // A string literal, closed by the given marker
+    String(String),
+}
+
+/// The comments of the block of contiguous comment lines that includes line
+/// `n`, or just the comment on line `n` if the line also holds code, each
+/// paired with the (1-based) line it is on
+pub fn comment_block(source: &[SourceLine], n: usize) -> Vec<(u64, &str)> {
+    let (start, end) = if source[n].is_comment_only() {
+        let start = source[..n]
+            .iter()
+            .rposition(|l| !l.is_comment_only())
+            .map_or(0, |i| i + 1);
+        let end = source[n..]
+            .iter()
+            .position(|l| !l.is_comment_only())
+            .map_or(source.len(), |i| n + i);
+        (start, end)
+    } else {
+        (n, n + 1)
+    };
+    (start..end)
+        .filter_map(|i| Some(((i + 1) as u64, source[i].comment.as_deref()?)))
+        .collect()
+}
+
+// The length of the string literal at the start of `s`, delimited by `delim`,
+// or the remainder of the line if the literal is not closed on it
+fn string_literal_len(s: &str, delim: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == delim => return i + c.len_utf8(),
+            _ => (),
+        }
+    }
+    s.len()
+}
+
+// The length of the opening of the raw string literal at the start of `s`,
+// like `r#"`, and the marker closing it
+fn raw_string_markers(s: &str) -> Option<(usize, String)> {
+    let caps = RUST_RAW_STRING_RE.captures(s)?;
+    Some((caps[0].len(), format!("\"{}", &caps["hashes"])))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn no_test_lines(lines: &[String]) -> Vec<bool> {
+    vec![false; lines.len()]
+}
+
+fn no_tagged_attributes(_code: &str) -> Vec<String> {
+    vec![]
+}
+
+fn no_item_of_line(_code: &str) -> Option<String> {
+    None
+}
+
+// The tags given in `#[implements(...)]` attributes in the `code`
+fn rust_tagged_attributes(code: &str) -> Vec<String> {
+    RUST_IMPLEMENTS_RE
+        .captures_iter(code)
+        .filter_map(|c| c.name("args"))
+        .flat_map(|args| {
+            STRING_LITERAL_RE
+                .captures_iter(args.as_str())
+                .filter_map(|c| c.name("content"))
+                .map(|m| m.as_str().to_string())
+                .collect::<Vec<String>>()
+        })
+        .collect()
+}
+
+// The kind and name of the rust item defined in the `code`, like `fn foo`, or
+// the trait and type of an impl, like `impl Display for Foo`
+fn rust_item_of_line(code: &str) -> Option<String> {
+    let captures = RUST_ITEM_RE.captures(code)?;
+    let kind = captures.name("kind")?.as_str();
+    let rest = captures.name("rest")?.as_str();
+    if kind == "impl" {
+        let rest = skip_generics(rest.trim_start());
+        let end = rest.find(['{', ';']).unwrap_or(rest.len());
+        let target = rest[..end].split(" where ").next().unwrap_or("").trim();
+        Some(format!("impl {}", target))
+    } else {
+        let name = IDENT_RE.captures(rest)?.name("ident")?.as_str();
+        Some(format!("{} {}", kind.trim_end_matches('!'), name))
+    }
+}
+
+// `s` without the generic parameters it starts with, if any
+fn skip_generics(s: &str) -> &str {
+    if !s.starts_with('<') {
+        return s;
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return s[i + 1..].trim_start();
+                }
+            }
+            _ => (),
+        }
+    }
+    s
+}
+
+fn is_test_attribute(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("#[")
+        && (line.contains("#[test]")
+            || RUST_CFG_TEST_RE.is_match(line)
+            || line.ends_with("::test]"))
+}
+
+fn is_comment_or_attribute_line(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("//") || line.starts_with("#[")
+}
+
+// For each of the `lines` of rust source, whether it is part of test code: an
+// item annotated with `#[test]` or `#[cfg(test)]`, including the comments and
+// attributes preceding it.
+//
+// This only tracks braces, so it can be fooled by braces in string literals.
+fn rust_test_lines(lines: &[String]) -> Vec<bool> {
+    let mut in_test = vec![false; lines.len()];
+    let mut depth = 0;
+    // The depth at which the current test item was opened
+    let mut test_depth: Option<usize> = None;
+    // Whether a test attribute was found, but its item isn't opened yet
+    let mut pending = false;
+    for (n, line) in lines.iter().enumerate() {
+        let code = line.split("//").next().unwrap_or("");
+        let mut is_test = test_depth.is_some();
+        if test_depth.is_none() && is_test_attribute(code) {
+            pending = true;
+        }
+        for c in code.chars() {
+            match c {
+                '{' => {
+                    if pending {
+                        test_depth = Some(depth);
+                        pending = false;
+                    }
+                    depth += 1;
+                }
+                '}' => {
+                    depth = depth.saturating_sub(1);
+                    if test_depth == Some(depth) {
+                        test_depth = None;
+                        is_test = true;
+                    }
+                }
+                // An item without a body, like `#[cfg(test)] mod test;`
+                ';' if pending && test_depth.is_none() => {
+                    pending = false;
+                    is_test = true;
+                }
+                _ => (),
+            }
+        }
+        in_test[n] = is_test || pending || test_depth.is_some();
+    }
+    // Doc comments and attributes above a test attribute belong to the test
+    for n in (0..lines.len().saturating_sub(1)).rev() {
+        if !in_test[n]
+            && in_test[n + 1]
+            && is_comment_or_attribute_line(&lines[n])
+            && is_comment_or_attribute_line(&lines[n + 1])
+        {
+            in_test[n] = true
+        }
+    }
+    in_test
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn comment_texts(lang: &Language, src: &str) -> Vec<Option<String>> {
+        let lines: Vec<String> = src.lines().map(String::from).collect();
+        lang.split_comments(&lines)
+            .into_iter()
+            .map(|l| l.comment)
+            .collect()
+    }
+
+    #[test]
+    fn only_finds_comments_outside_of_strings() {
+        let src = r#"let s = "// |NOT.1|"; // |FOO.1|
+/* |BAR.1|
+   continued */ let t = '/';"#;
+        let expected = vec![
+            Some(" |FOO.1|".to_string()),
+            Some(" |BAR.1|".to_string()),
+            Some("   continued".to_string()),
+        ];
+        assert_eq!(comment_texts(&TYPESCRIPT, src), expected);
+    }
+
+    #[test]
+    fn finds_python_comments_and_docstrings() {
+        let src = r##"def foo():
+    """|FOO.1|"""
+    return "# |NOT.1|"  # |BAR.1|"##;
+        let expected = vec![
+            None,
+            Some("|FOO.1|".to_string()),
+            Some(" |BAR.1|".to_string()),
+        ];
+        assert_eq!(comment_texts(&PYTHON, src), expected);
+    }
+
+    #[test]
+    fn only_treats_docstrings_as_comments() {
+        let src = r##""""|MOD.1|"""
+
+class Foo:
+    """|FOO.1|
+    # still the docstring
+    """
+
+    def bar(
+        self,
+    ) -> str:
+        """|BAR.1|"""
+        return """
+        # |NOT.1|
+        """
+
+def baz(): """|BAZ.1|"""
+x = """|NOT.2|""""##;
+        let expected = vec![
+            Some("|MOD.1|".to_string()),
+            None,
+            None,
+            Some("|FOO.1|".to_string()),
+            Some("    # still the docstring".to_string()),
+            Some("".to_string()),
+            None,
+            None,
+            None,
+            None,
+            Some("|BAR.1|".to_string()),
+            None,
+            None,
+            None,
+            None,
+            Some("|BAZ.1|".to_string()),
+            None,
+        ];
+        assert_eq!(comment_texts(&PYTHON, src), expected);
+    }
+
+    #[test]
+    fn skips_rust_raw_strings() {
+        let src = r###"let s = r#"a " // |NOT.1|"#; // |FOO.1|
+let t = br"\"; // |BAR.1|
+let u = r##"
+/* |NOT.2| */
+"##; // |BAZ.1|"###;
+        let expected = vec![
+            Some(" |FOO.1|".to_string()),
+            Some(" |BAR.1|".to_string()),
+            None,
+            None,
+            Some(" |BAZ.1|".to_string()),
+        ];
+        assert_eq!(comment_texts(&RUST, src), expected);
+    }
+
+    #[test]
+    fn can_recognize_test_files() {
+        assert!(GO.is_test_file(Path::new("pkg/foo_test.go")));
+        assert!(!GO.is_test_file(Path::new("pkg/foo.go")));
+        assert!(TYPESCRIPT.is_test_file(Path::new("src/foo.test.ts")));
+        assert!(PYTHON.is_test_file(Path::new("test_foo.py")));
+    }
+
+    #[test]
+    fn finds_tags_in_rust_attributes() {
+        assert_eq!(
+            rust_tagged_attributes(r#"#[kontxt_macros::implements("FOO.1", "FOO.1::BAR.1")]"#),
+            vec!["FOO.1".to_string(), "FOO.1::BAR.1".to_string()]
+        );
+        assert!(rust_tagged_attributes(r#"#[derive(Debug)]"#).is_empty());
+    }
+
+    #[test]
+    fn finds_rust_test_lines() {
+        let src = "fn foo() {}\n#[cfg(all(test, unix))]\nmod test {\n}\n#[cfg(unix)]\nfn bar() {}";
+        let lines: Vec<String> = src.lines().map(String::from).collect();
+        assert_eq!(
+            rust_test_lines(&lines),
+            vec![false, true, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn describes_rust_items() {
+        let cases = vec![
+            ("pub(crate) async fn foo<T>(t: T) {", Some("fn foo")),
+            ("struct Foo(u8);", Some("struct Foo")),
+            ("const MAX: u8 = 1;", Some("const MAX")),
+            ("pub const fn max() -> u8 {", Some("fn max")),
+            (
+                "impl<T: Debug> fmt::Display for Foo<T> {",
+                Some("impl fmt::Display for Foo<T>"),
+            ),
+            ("impl Foo where Foo: Sized {", Some("impl Foo")),
+            ("macro_rules! foo {", Some("macro_rules foo")),
+            ("let x = 1;", None),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(rust_item_of_line(code).as_deref(), expected, "{}", code);
+        }
+    }
+}
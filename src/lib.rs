@@ -8,16 +8,23 @@
 pub mod cmd;
 
 mod artifact;
+mod check;
+mod config;
 mod context;
 mod db;
 mod dot;
 mod envvar;
 mod graph;
+mod hosting;
+mod language;
 mod linkify;
 mod locations;
 mod logical_unit;
+mod markdown;
+mod model;
 mod pandoc;
 mod parser;
 mod repo;
+mod rust;
 mod site;
 mod util;
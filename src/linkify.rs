@@ -1,6 +1,6 @@
 use {
     crate::{
-        db, pandoc,
+        db, markdown, pandoc,
         parser::{parser, UnitRefSearch, TAG_ID_ESCAPED_RE},
     },
     anyhow::{Context as AnyhowContext, Result},
@@ -21,6 +21,8 @@ use {
 pub enum Error {
     #[error("Error parsing html during linkification: {0}")]
     ParsingHtml(String),
+    #[error("References to missing units:\n{}", .0.join("\n"))]
+    DanglingReferences(Vec<String>),
 }
 
 /// `file_via_pandoc(&conn, &path, true)` will linkify the content of the file
@@ -46,7 +48,26 @@ pub fn file_via_pandoc(conn: &sql::Connection, path: &path::Path, gfm: bool) ->
     Ok(())
 }
 
+/// `dangling_refs(&conn, s)` is a description of every reference in the markdown
+/// string `s` to a unit that is not registered in the db, giving the line on
+/// which the reference occurs.
+fn dangling_refs(conn: &sql::Connection, s: &str) -> Result<Vec<String>> {
+    let mut dangling = Vec::new();
+    for unit_ref in markdown::unit_refs(s) {
+        if db::unit::get(conn, &unit_ref.tag)?.is_none() {
+            dangling.push(format!("line {}: {}", unit_ref.line, unit_ref.tag))
+        }
+    }
+    Ok(dangling)
+}
+
 pub fn string_via_pandoc(conn: &sql::Connection, s: &str, gfm: bool) -> Result<String> {
+    // Report all the broken references up front, rather than failing on the
+    // first one we encounter while rewriting
+    let dangling = dangling_refs(conn, s)?;
+    if !dangling.is_empty() {
+        return Err(Error::DanglingReferences(dangling).into());
+    }
     let bytes = &pandoc::parse_string(s)?;
     let html = String::from_utf8_lossy(bytes);
     let new_html = linkify_spec_string(Some(conn), &html, gfm).context("linkifying string")?;
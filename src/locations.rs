@@ -20,6 +20,7 @@ enum Error {
 
 pub const WORKSITE_FILE_NAME: &str = ".tracer";
 pub const CONTEXTS_DIR_NAME: &str = "contexts";
+pub const REPOS_DIR_NAME: &str = "repos";
 
 // TODO DOcument TRACER_HOME var
 /// The directory used as HOME for tracer
@@ -50,6 +51,13 @@ pub fn contexts_dir() -> Result<PathBuf> {
     Ok(path)
 }
 
+/// Directory in which remote repos are cloned
+pub fn repos_dir() -> Result<PathBuf> {
+    let mut path = tracer_dir()?;
+    path.push(REPOS_DIR_NAME);
+    Ok(path)
+}
+
 fn is_hidden(entry: &walkdir::DirEntry) -> bool {
     entry
         .file_name()
@@ -62,6 +70,16 @@ fn source_file_is_supported(entry: &walkdir::DirEntry) -> bool {
     !is_hidden(entry) && artifact::SourceFileKind::try_from(entry.path()).is_ok()
 }
 
+/// `is_supported_source_path(path)` is `true` if the file at `path` is not
+/// hidden and is of a supported kind
+pub fn is_supported_source_path(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'));
+    !hidden && artifact::SourceFileKind::try_from(path).is_ok()
+}
+
 pub fn find_all_supported_source_files(p: &Path) -> Result<Vec<PathBuf>> {
     // FIXME Avoid the tripper into iter
     Ok(walkdir::WalkDir::new(p)
@@ -73,3 +91,15 @@ pub fn find_all_supported_source_files(p: &Path) -> Result<Vec<PathBuf>> {
         .map(|e| e.into_path())
         .collect())
 }
+
+/// `find_all_files(p)` is every file under the directory `p` (or `p` itself,
+/// if it is a file) that is not hidden
+pub fn find_all_files(p: &Path) -> Result<Vec<PathBuf>> {
+    Ok(walkdir::WalkDir::new(p)
+        .into_iter()
+        .collect::<walkdir::Result<Vec<walkdir::DirEntry>>>()? // Bail if we hit any errors
+        .into_iter()
+        .filter(|e| e.file_type().is_file() && !is_hidden(e))
+        .map(|e| e.into_path())
+        .collect())
+}
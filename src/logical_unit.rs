@@ -2,8 +2,10 @@ use {
     crate::{parser::parser, repo::Repo, util},
     serde::{de, Deserialize, Deserializer, Serialize, Serializer},
     std::{
+        collections::BTreeMap,
         fmt,
         path::{Path, PathBuf},
+        str::FromStr,
     },
 };
 
@@ -22,6 +24,11 @@ impl Id {
     fn parts(&self) -> Vec<(String, u32)> {
         self.parts.clone()
     }
+
+    /// The prefix of the id, e.g., `FOO` for `FOO.1::BAR.2`
+    pub fn prefix(&self) -> &str {
+        &self.parts[0].0
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -34,6 +41,25 @@ pub enum Kind {
     Verification,
 }
 
+impl Kind {
+    /// Whether units of this kind define their tag. A tag has at most one
+    /// defining unit, but may occur in any number of units implementing,
+    /// verifying, or modeling it.
+    pub fn is_definition(&self) -> bool {
+        matches!(self, Kind::Requirement)
+    }
+}
+
+/// A reference made by a unit to a logical unit, like `[FOO.1::BAR.1]`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Reference {
+    pub id: Id,
+    /// The line of the source on which the reference is first made, if it is
+    /// known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct LogicalUnit {
     pub id: Id,
@@ -41,9 +67,13 @@ pub struct LogicalUnit {
     pub repo: Option<Repo>,
     pub file: Option<PathBuf>,
     pub line: Option<u64>,
+    /// The first and last lines of the item tagged with the unit, for units
+    /// tagging items in code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<(u64, u64)>,
     pub content: String,
     /// Logical units that are referred to in the content of this one
-    pub references: Vec<Id>, // TODO
+    pub references: Vec<Reference>,
 }
 
 impl LogicalUnit {
@@ -64,6 +94,7 @@ impl LogicalUnit {
             repo,
             file,
             line,
+            span: None,
             content: content.to_string(),
             references,
         })
@@ -88,6 +119,33 @@ impl LogicalUnit {
         path.into_os_string().into_string().ok()
     }
 
+    /// `unit.location()` is a human readable description of where the `unit`
+    /// is defined, in the form `repo/file:line`, omitting any parts that are
+    /// unknown
+    pub fn location(&self) -> String {
+        self.location_at(self.line)
+    }
+
+    /// `unit.location_at(line)` is as [LogicalUnit::location], but describes
+    /// the given `line` of the `unit`'s file
+    pub fn location_at(&self, line: Option<u64>) -> String {
+        let mut path = self.repo.as_ref().map_or(PathBuf::new(), |r| r.path());
+        match &self.file {
+            Some(file) => path.push(file),
+            None if self.repo.is_none() => return "<unknown>".into(),
+            None => (),
+        };
+        match line {
+            Some(line) => format!("{}:{}", path.display(), line),
+            None => path.display().to_string(),
+        }
+    }
+
+    /// Whether the unit is the definition of its tag
+    pub fn is_definition(&self) -> bool {
+        self.kind.is_definition()
+    }
+
     /// The id of the unit's parent unit, or None, if the unit is an urunit.
     pub fn parent_id(&self) -> Option<Id> {
         self.id.parts.split_last().and_then(|(_, parts)| {
@@ -98,9 +156,63 @@ impl LogicalUnit {
     }
 }
 
-// TODO
-fn references_of_content(_s: &str) -> Vec<Id> {
-    vec![]
+/// `occurrences(units)` groups the `units` by their ids. The units with each id
+/// are in the order given, except that the defining unit, if any, comes first.
+pub fn occurrences(units: &[LogicalUnit]) -> BTreeMap<&Id, Vec<&LogicalUnit>> {
+    let mut by_id: BTreeMap<&Id, Vec<&LogicalUnit>> = BTreeMap::new();
+    for unit in units {
+        by_id.entry(&unit.id).or_default().push(unit);
+    }
+    for units in by_id.values_mut() {
+        units.sort_by_key(|u| !u.is_definition());
+    }
+    by_id
+}
+
+/// `references_of_content(s)` is the references to all the logical units
+/// referenced (e.g., as `[FOO.1::BAR.1]`) in the string `s`, in order of first
+/// occurrence.
+///
+/// References that are not valid logical unit ids are ignored.
+pub(crate) fn references_of_content(s: &str) -> Vec<Reference> {
+    references_of_tags(
+        parser::logical_unit_refs_at(s)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, tag)| (tag, None)),
+    )
+}
+
+/// `references_of_source(pieces)` is as [references_of_content], but for
+/// text taken from a source in `pieces`, each paired with the line on which
+/// it starts, so that the line of each reference is known.
+pub(crate) fn references_of_source<'a>(
+    pieces: impl IntoIterator<Item = (u64, &'a str)>,
+) -> Vec<Reference> {
+    references_of_tags(pieces.into_iter().flat_map(|(line, s)| {
+        parser::logical_unit_refs_at(s)
+            .unwrap_or_default()
+            .into_iter()
+            .map(move |(offset, tag)| {
+                let line = line + s[..offset].matches('\n').count() as u64;
+                (tag, Some(line))
+            })
+    }))
+}
+
+/// `references_of_tags(tags)` is the references to the valid ids among the
+/// `tags`, each paired with the line on which it occurs, if known. Only the
+/// first reference to each id is kept.
+fn references_of_tags(tags: impl IntoIterator<Item = (String, Option<u64>)>) -> Vec<Reference> {
+    let mut refs: Vec<Reference> = Vec::new();
+    for (tag, line) in tags {
+        match Id::new(&tag) {
+            Ok(id) if !refs.iter().any(|r| r.id == id) => refs.push(Reference { id, line }),
+            Ok(_) => (),
+            Err(_) => log::warn!("ignoring invalid reference to {}", tag),
+        }
+    }
+    refs
 }
 
 impl Serialize for Id {
@@ -164,6 +276,20 @@ impl fmt::Display for Kind {
     }
 }
 
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "requirement" => Ok(Kind::Requirement),
+            "model" => Ok(Kind::Model),
+            "implementation" => Ok(Kind::Implementation),
+            "verification" => Ok(Kind::Verification),
+            _ => Err(format!("unknown kind of logical unit {}", s)),
+        }
+    }
+}
+
 impl fmt::Display for LogicalUnit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let file = self
@@ -204,6 +330,39 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn references_are_parsed_from_content() {
+        let unit = LogicalUnit::new(
+            None,
+            None,
+            None,
+            Kind::Requirement,
+            "FOO.1::BAR.1",
+            "Refers to [FOO.1] and [FIZ.1::BAZ.2], and again to [FOO.1]",
+        )
+        .unwrap();
+        let actual: Vec<Id> = unit.references.into_iter().map(|r| r.id).collect();
+        let expected = vec![Id::new("FOO.1").unwrap(), Id::new("FIZ.1::BAZ.2").unwrap()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn references_record_their_lines() {
+        let actual: Vec<(String, Option<u64>)> = references_of_source(vec![
+            (3, "See [FOO.1]\nand [BAR.1]"),
+            (7, "and [FOO.1] again, and [BAZ.1]"),
+        ])
+        .into_iter()
+        .map(|r| (r.id.to_string(), r.line))
+        .collect();
+        let expected = vec![
+            ("FOO.1".to_string(), Some(3)),
+            ("BAR.1".to_string(), Some(4)),
+            ("BAZ.1".to_string(), Some(7)),
+        ];
+        assert_eq!(actual, expected);
+    }
+
     // TODO Add unit tests for serde
     // #[test]
     // fn csv_serialize_unit() {
@@ -0,0 +1,598 @@
+//!
+//! Extraction of definition lists from markdown
+//!
+//! By default, markdown is parsed in process. When built with the `pandoc`
+//! feature, the pandoc executable is used to parse markdown instead.
+//!
+//! Only the in process parser records the source lines of terms. Pandoc's
+//! markdown reader does not report source positions, so the definitions it
+//! parses have no lines, and neither do the references made in them.
+//!
+
+use {
+    crate::parser::parser, anyhow::Result, itertools::Itertools, std::path::Path, thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Parsing definition list. Encountered {0} while parsing `{1}`")]
+    DefinitionListParsing(String, String),
+}
+
+/// A term and its definition, taken from a definition list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub term: String,
+    /// The line of the source on which the term appears, if it is known. It is
+    /// never known when parsing with pandoc.
+    pub line: Option<u64>,
+    pub content: String,
+}
+
+// The attribute used to record the source line of a term in the generated html
+const LINE_ATTR: &str = "data-line";
+
+// The extensions enabled approximate pandoc's markdown
+fn options() -> pulldown_cmark::Options {
+    use pulldown_cmark::Options;
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_DEFINITION_LIST);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options
+}
+
+// The 1-based line number of the byte `offset` into `s`
+fn line_of_offset(s: &str, offset: usize) -> u64 {
+    s[..offset].matches('\n').count() as u64 + 1
+}
+
+// The fence opening or closing a fenced code block on a `line`, with its
+// leading spaces trimmed, if any
+fn fence_of(line: &str) -> Option<&str> {
+    ["```", "~~~"].iter().find_map(|marker| {
+        line.starts_with(marker).then(|| {
+            let c = marker.chars().next().unwrap();
+            &line[..line.len() - line.trim_start_matches(c).len()]
+        })
+    })
+}
+
+// Pandoc lets the items of a list in a definition continue lazily, indented
+// less than the content of the definition, e.g.,
+//
+//     : - Foo
+//      - Bar
+//
+// So such items are indented to the content of their definition. Only spaces
+// are added, so the lines of the source are preserved. Fenced code is left as
+// it is. Indented code is never changed, since it can't follow a definition
+// without a blank line.
+fn indent_lazy_items(s: &str) -> String {
+    let is_item = |line: &str| {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        ["- ", "* ", "+ "].iter().any(|m| line.starts_with(m))
+            || (digits > 0 && line[digits..].starts_with(". "))
+    };
+    let mut indented = String::with_capacity(s.len());
+    // The column of the content of the current definition
+    let mut content_col: Option<usize> = None;
+    // The fence of the code block the line is in, if any
+    let mut fence: Option<&str> = None;
+    for line in s.split_inclusive('\n') {
+        let text = line.trim_start_matches(' ');
+        let indent = line.len() - text.len();
+        if let Some(open) = fence {
+            if fence_of(text).is_some_and(|close| close.starts_with(open)) {
+                fence = None;
+            }
+        } else if let Some(open) = fence_of(text) {
+            fence = Some(open);
+            content_col = None;
+        } else if text.trim().is_empty() {
+            content_col = None;
+        } else if let Some(content) = text.strip_prefix(':') {
+            let spaces = content.len() - content.trim_start_matches(' ').len();
+            if spaces > 0 && indent < 4 {
+                content_col = Some(indent + 1 + spaces);
+            }
+        } else if let Some(col) = content_col.filter(|col| indent < *col && is_item(text)) {
+            indented.push_str(&" ".repeat(col - indent));
+        }
+        indented.push_str(line);
+    }
+    indented
+}
+
+#[cfg(not(feature = "pandoc"))]
+mod backend {
+    use {
+        anyhow::Result,
+        lazy_static::lazy_static,
+        pulldown_cmark::{html, CowStr, Event, Parser, Tag, TagEnd},
+        regex::Regex,
+        std::{fs, path::Path},
+    };
+
+    lazy_static! {
+        // Matches pandoc's bracketed spans, like `[text]{.class}`
+        static ref BRACKETED_SPAN_RE: Regex = Regex::new(r"\[([^\[\]]*)\]\{([^{}]*)\}").unwrap();
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    // Whether the `key` of a pandoc attribute can be written as the name of
+    // an html attribute. Other keys are dropped, so they can't add attributes
+    // of their own.
+    fn is_attr_name(key: &str) -> bool {
+        let mut chars = key.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+    }
+
+    // The html attributes of a span with the pandoc `attrs`, like
+    // `#id .class key=value`
+    fn span_attrs(attrs: &str) -> String {
+        let value = |v: &str| escape(v.trim_matches('"'));
+        let mut classes = Vec::new();
+        let mut html = String::new();
+        for attr in attrs.split_whitespace() {
+            if let Some(class) = attr.strip_prefix('.') {
+                classes.push(value(class));
+            } else if let Some(id) = attr.strip_prefix('#') {
+                html.push_str(&format!(" id=\"{}\"", value(id)));
+            } else if let Some((key, v)) = attr.split_once('=').filter(|(k, _)| is_attr_name(k)) {
+                html.push_str(&format!(" {}=\"{}\"", key, value(v)));
+            }
+        }
+        if !classes.is_empty() {
+            html.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+        }
+        html
+    }
+
+    // Renders the bracketed spans in the `text` as html
+    fn with_spans(text: String, events: &mut Vec<Event>) {
+        let mut rest = 0;
+        for span in BRACKETED_SPAN_RE.captures_iter(&text) {
+            let (whole, content, attrs) = (span.get(0).unwrap(), &span[1], &span[2]);
+            events.push(Event::Text(text[rest..whole.start()].to_string().into()));
+            events.push(Event::InlineHtml(
+                format!("<span{}>", span_attrs(attrs)).into(),
+            ));
+            events.push(Event::Text(content.to_string().into()));
+            events.push(Event::InlineHtml("</span>".into()));
+            rest = whole.end();
+        }
+        events.push(Event::Text(text[rest..].to_string().into()));
+    }
+
+    // Pandoc renders the text of a paragraph in pieces, so the text is joined
+    // before looking for spans. Text in code is left alone.
+    fn render_spans(events: Vec<Event>) -> Vec<Event> {
+        let mut rendered = Vec::with_capacity(events.len());
+        let mut text: Option<String> = None;
+        let mut in_code = false;
+        for event in events {
+            match event {
+                Event::Text(t) if !in_code => text.get_or_insert_with(String::new).push_str(&t),
+                event => {
+                    if let Some(text) = text.take() {
+                        with_spans(text, &mut rendered);
+                    }
+                    match event {
+                        Event::Start(Tag::CodeBlock(_)) => in_code = true,
+                        Event::End(TagEnd::CodeBlock) => in_code = false,
+                        _ => (),
+                    }
+                    rendered.push(event);
+                }
+            }
+        }
+        if let Some(text) = text {
+            with_spans(text, &mut rendered);
+        }
+        rendered
+    }
+
+    // Pandoc takes an image alone in a paragraph as a figure, titled `fig:`,
+    // which shows when the paragraph is rendered as the item of a tight list
+    fn title_figures(events: &mut [Event]) {
+        for i in 1..events.len() {
+            let alone = matches!(events[i - 1], Event::Start(Tag::Item))
+                && events[i..]
+                    .iter()
+                    .position(|e| matches!(e, Event::End(TagEnd::Image)))
+                    .is_some_and(|end| {
+                        matches!(events.get(i + end + 1), Some(Event::End(TagEnd::Item)))
+                    });
+            if let Event::Start(Tag::Image { title, .. }) = &mut events[i] {
+                if alone && title.is_empty() {
+                    *title = CowStr::from("fig:");
+                }
+            }
+        }
+    }
+
+    pub fn html_of_string(s: &str) -> Result<String> {
+        let s = &super::indent_lazy_items(s);
+        let parser = Parser::new_ext(s, super::options()).into_offset_iter();
+        let events = parser.map(|(event, range)| match event {
+            // Soft line breaks are rendered as spaces, as pandoc does, so that
+            // the content of units doesn't depend on how the source was wrapped.
+            Event::SoftBreak => Event::Text(" ".into()),
+            // Terms record the line they start on, so units can be located
+            Event::Start(Tag::DefinitionListTitle) => Event::Html(
+                format!(
+                    "<dt {}=\"{}\">",
+                    super::LINE_ATTR,
+                    super::line_of_offset(s, range.start)
+                )
+                .into(),
+            ),
+            event => event,
+        });
+        let mut events = render_spans(events.collect());
+        title_figures(&mut events);
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        Ok(html)
+    }
+
+    pub fn html_of_file(path: &Path) -> Result<String> {
+        html_of_string(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(feature = "pandoc")]
+mod backend {
+    use {crate::pandoc, anyhow::Result, std::path::Path};
+
+    pub fn html_of_string(s: &str) -> Result<String> {
+        pandoc::parse_string(s).map(|bytes| String::from_utf8_lossy(&bytes).into())
+    }
+
+    pub fn html_of_file(path: &Path) -> Result<String> {
+        pandoc::parse_file(path)
+    }
+}
+
+// A run of inline text, as parsed from markdown
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InlineText {
+    text: String,
+    // The offsets into the `text` at which each source line begins
+    lines: Vec<(usize, u64)>,
+}
+
+impl InlineText {
+    // The source line of the character at `offset` into the `text`
+    fn line_at(&self, offset: usize) -> u64 {
+        self.lines
+            .iter()
+            .take_while(|(start, _)| *start <= offset)
+            .last()
+            .map_or(1, |(_, line)| *line)
+    }
+}
+
+// Every run of inline text in the markdown string `s`, outside of code. The
+// lines of a wrapped paragraph are joined without a separator, so that text
+// broken over a line (like a tag) is found whole.
+fn inline_texts(s: &str) -> Vec<InlineText> {
+    use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+    let s = &indent_lazy_items(s);
+    let mut texts = Vec::new();
+    let mut run: Option<InlineText> = None;
+    let mut in_code = false;
+    for (event, range) in Parser::new_ext(s, options()).into_offset_iter() {
+        match event {
+            Event::Text(t) if !in_code => {
+                let line = line_of_offset(s, range.start);
+                let run = run.get_or_insert_with(|| InlineText {
+                    text: String::new(),
+                    lines: Vec::new(),
+                });
+                if run.lines.last().map(|(_, l)| *l) != Some(line) {
+                    run.lines.push((run.text.len(), line));
+                }
+                run.text.push_str(&t);
+            }
+            Event::SoftBreak => (),
+            event => {
+                match event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code = true,
+                    Event::End(TagEnd::CodeBlock) => in_code = false,
+                    _ => (),
+                }
+                texts.extend(run.take())
+            }
+        }
+    }
+    texts.extend(run);
+    texts
+}
+
+/// A reference to a logical unit, like `[FOO.1]`, made in markdown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitRef {
+    pub tag: String,
+    /// The line of the source on which the reference starts
+    pub line: u64,
+}
+
+/// `unit_refs(s)` is every reference to a logical unit made in the markdown
+/// string `s`, in order. References in code are not included.
+pub fn unit_refs(s: &str) -> Vec<UnitRef> {
+    inline_texts(s)
+        .iter()
+        .flat_map(|text| {
+            parser::logical_unit_refs_at(&text.text)
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |(offset, tag)| UnitRef {
+                    tag,
+                    line: text.line_at(offset),
+                })
+        })
+        .collect()
+}
+
+fn def_parsing_err(msg: &str, element: scraper::ElementRef) -> anyhow::Error {
+    Error::DefinitionListParsing(msg.to_string(), element.html()).into()
+}
+
+// Get the first child and wrap it back up into an ElementRef (if it exists)
+fn get_child_element(el: scraper::ElementRef) -> Option<scraper::ElementRef> {
+    el.first_child().and_then(scraper::ElementRef::wrap)
+}
+
+// Finds a logical unit tag from a def list term, even if it's wrapped in
+// (possibly nested) inline HTML tags.
+fn tag_of_term(term: scraper::ElementRef) -> Option<String> {
+    match get_child_element(term) {
+        None => Some(term.inner_html()),
+        Some(child) => tag_of_term(child),
+    }
+}
+
+// The line recorded on a def list term, if any
+fn line_of_term(term: scraper::ElementRef) -> Option<u64> {
+    term.value().attr(LINE_ATTR).and_then(|l| l.parse().ok())
+}
+
+fn definitions_from_html(html: scraper::Html) -> Result<Vec<Definition>> {
+    // Beware, yucky imperative programming ahead :(
+    let mut defs = Vec::new();
+
+    // These unwraps should only fail if invalid selectors are constructed,
+    // but this is effectively a constant we know at build time and exercise in
+    // our tests
+    let def_lists = scraper::Selector::parse("dl").unwrap();
+    // Either a def term or a definition
+    let def_item = scraper::Selector::parse("dt, dd").unwrap();
+
+    for def_list in html.select(&def_lists) {
+        // Group dt and dd elems together
+        let grouped_elements = def_list.select(&def_item).group_by(|el| el.value().name());
+        // Arrange
+        let definitions = grouped_elements.into_iter().tuples();
+        for ((term_tag, terms_group), (defs_tag, defs_group)) in definitions {
+            if !(term_tag == "dt" && defs_tag == "dd") {
+                // This generally shouldn't occur, since a definition list without a
+                // leading dt can't appear in markdown. But it could arise from
+                // HTML embedded in a markdown doc.
+                return Err(def_parsing_err("invalid tags on def list groups", def_list));
+            }
+
+            let terms: Vec<scraper::ElementRef> = terms_group.collect();
+
+            // TODO Can we dispense with tese imperative returns?
+            let (tag, line) = match terms[..] {
+                [term] => {
+                    if let Some(tag) = tag_of_term(term) {
+                        (tag, line_of_term(term))
+                    } else {
+                        return Err(def_parsing_err(
+                            "could not parse html of definition term",
+                            def_list,
+                        ));
+                    }
+                }
+                // This should be impossbile
+                [] => return Err(def_parsing_err("no definition terms", def_list)),
+                _ => {
+                    return Err(def_parsing_err(
+                        "multiple definition terms (not yet supported)",
+                        def_list,
+                    ))
+                }
+            };
+
+            let content = defs_group
+                .map(|el| html2md::parse_html(&el.html()))
+                .join("\n\n");
+
+            defs.push(Definition {
+                term: tag,
+                line,
+                content,
+            });
+        }
+    }
+    Ok(defs)
+}
+
+/// `definitions_from_file(path)` is a list of the terms and definitions in all
+/// the definition lists of the markdown file at `path`.
+pub fn definitions_from_file(path: &Path) -> Result<Vec<Definition>> {
+    let html = backend::html_of_file(path)?;
+    definitions_from_html(scraper::Html::parse_fragment(&html))
+}
+
+/// As [definitions_from_file], but reading the markdown from the string `s`.
+pub fn definitions_from_string(s: &str) -> Result<Vec<Definition>> {
+    let html = backend::html_of_string(s)?;
+    definitions_from_html(scraper::Html::parse_fragment(&html))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_def_list_with_naked_term() {
+        let input = r#"
+|FOO.1::BAR.1|
+:  Biz baz blam.
+"#;
+        let actual: Vec<(String, String)> = definitions_from_string(input)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.term, d.content))
+            .collect();
+        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn find_def_list_with_wrapped_term() {
+        let input = r#"
+<a id="FOO.1::BAR.1">|FOO.1::BAR.1|</a>
+:  Biz baz blam.
+"#;
+        let actual: Vec<(String, String)> = definitions_from_string(input)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.term, d.content))
+            .collect();
+        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn find_def_list_with_nestd_wrapped_term() {
+        let input = r#"
+<a id="FOO.1::BAR.1"><em>|FOO.1::BAR.1|</em></a>
+:  Biz baz blam.
+"#;
+        let actual: Vec<(String, String)> = definitions_from_string(input)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.term, d.content))
+            .collect();
+        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
+        assert_eq!(actual, expected)
+    }
+
+    #[cfg(not(feature = "pandoc"))]
+    #[test]
+    fn find_lazily_indented_items_of_definitions() {
+        let input = r#"
+|FOO.1|
+: - Foo
+ - [Bar]{.smallcaps}
+ - ![baz](/url)
+"#;
+        let actual: Vec<String> = definitions_from_string(input)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.content)
+            .collect();
+        assert_eq!(actual, vec!["* Foo\n* Bar\n* ![baz](/url \"fig:\")"])
+    }
+
+    #[cfg(not(feature = "pandoc"))]
+    #[test]
+    fn code_is_not_rewritten() {
+        let input = r#"
+```
+: - Foo
+ - [Bar]{.smallcaps}
+```
+"#;
+        assert_eq!(indent_lazy_items(input), input);
+        let html = backend::html_of_string(input).unwrap();
+        assert!(html.contains("\n - [Bar]{.smallcaps}\n"), "{}", html);
+    }
+
+    #[cfg(not(feature = "pandoc"))]
+    #[test]
+    fn attributes_of_spans_are_escaped() {
+        let html = backend::html_of_string("[Foo]{#a&b .c>d x>y=z data-x=a>b}").unwrap();
+        assert_eq!(
+            html,
+            "<p><span id=\"a&amp;b\" data-x=\"a&gt;b\" class=\"c&gt;d\">Foo</span></p>\n"
+        );
+    }
+
+    #[test]
+    fn inline_texts_join_wrapped_lines() {
+        let input = r#"# Spec
+
+See [FOO.1] and
+[FOO.1::
+BAR.1].
+"#;
+        let texts = inline_texts(input);
+        let text = &texts[1];
+        assert_eq!(text.text, "See [FOO.1] and[FOO.1::BAR.1].");
+        assert_eq!(text.line_at(4), 3);
+        assert_eq!(text.line_at(15), 4);
+        assert_eq!(text.line_at(24), 5);
+    }
+
+    #[test]
+    fn unit_refs_skip_code() {
+        let input = r#"See [FOO.1] and `[NOT.1]`.
+
+```
+[NOT.2]
+```
+
+    [NOT.3]
+
+Then [FOO.1::
+BAR.1], with \\[FOO.2].
+"#;
+        let actual: Vec<(String, u64)> = unit_refs(input)
+            .into_iter()
+            .map(|r| (r.tag, r.line))
+            .collect();
+        let expected = vec![
+            ("FOO.1".to_string(), 1),
+            ("FOO.1::BAR.1".to_string(), 9),
+            ("FOO.2".to_string(), 10),
+        ];
+        assert_eq!(actual, expected)
+    }
+
+    #[cfg(not(feature = "pandoc"))]
+    #[test]
+    fn find_lines_of_terms() {
+        let input = r#"# Spec
+
+|FOO.1|
+:  Foo.
+
+|FOO.1::BAR.1|
+:  Bar,
+   on two lines.
+"#;
+        let actual: Vec<Option<u64>> = definitions_from_string(input)
+            .unwrap()
+            .iter()
+            .map(|d| d.line)
+            .collect();
+        assert_eq!(actual, vec![Some(3), Some(6)])
+    }
+}
@@ -0,0 +1,316 @@
+//!
+//! Extraction of tagged comments from TLA+, Quint, and Alloy models
+//!
+//! A logical unit is tagged in a comment, and is taken to describe the
+//! operator defined right after the comment.
+//!
+
+use {
+    crate::{
+        logical_unit::{self, Reference},
+        parser::TAG_ID_RE,
+    },
+    lazy_static::lazy_static,
+    regex::Regex,
+    std::{convert::TryInto, ops::Range},
+};
+
+lazy_static! {
+    /// Matches the start of a TLA+ operator definition, like `Next == ...`,
+    /// `Add(a, b) == ...`, or `LOCAL Helper == ...`
+    static ref TLA_OPERATOR_RE: Regex =
+        Regex::new(r"^\s*(LOCAL\s+)?(?P<name>[A-Za-z_][A-Za-z0-9_]*)\s*(\(.*\)|\[.*\])?\s*==").unwrap();
+
+    /// Matches the start of a Quint definition, like `pure def add(a, b) = ...`
+    /// or `action step = ...`
+    static ref QUINT_OPERATOR_RE: Regex = Regex::new(
+        r"^\s*(pure\s+)?(def|val|action|temporal|run|nondet|const|var|type)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)"
+    )
+    .unwrap();
+
+    /// Matches the start of an Alloy paragraph, like `sig File {...}`,
+    /// `pred add[f: File] {...}`, or `abstract sig Object {}`
+    static ref ALLOY_PARAGRAPH_RE: Regex = Regex::new(
+        r"^\s*((abstract|one|lone|some|private|var)\s+)*(sig|pred|fun|fact|assert|run|check|enum)\s+(?P<name>[A-Za-z_][A-Za-z0-9_']*)"
+    )
+    .unwrap();
+}
+
+/// The languages models can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Tla,
+    Quint,
+    Alloy,
+}
+
+impl Language {
+    fn line_comments(&self) -> &'static [&'static str] {
+        match self {
+            Language::Tla => &[r"\*"],
+            Language::Quint => &["//"],
+            Language::Alloy => &["//", "--"],
+        }
+    }
+
+    fn block_comment(&self) -> (&'static str, &'static str) {
+        match self {
+            Language::Tla => ("(*", "*)"),
+            Language::Quint | Language::Alloy => ("/*", "*/"),
+        }
+    }
+
+    fn operator_re(&self) -> &'static Regex {
+        match self {
+            Language::Tla => &TLA_OPERATOR_RE,
+            Language::Quint => &QUINT_OPERATOR_RE,
+            Language::Alloy => &ALLOY_PARAGRAPH_RE,
+        }
+    }
+
+    // The name of the operator defined on `line`, if any
+    fn operator_defined(&self, line: &str) -> Option<String> {
+        self.operator_re()
+            .captures(line)
+            .and_then(|c| c.name("name"))
+            .map(|m| m.as_str().to_string())
+    }
+}
+
+/// A tag found in the comments of a model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedComment {
+    pub tag: String,
+    /// The (1-based) line on which the tag appears
+    pub line: u64,
+    /// The name of the operator defined after the comment, if any
+    pub operator: Option<String>,
+    /// The text of the comment, without the comment markers or tags
+    pub text: String,
+    /// The references made in the comment
+    pub references: Vec<Reference>,
+}
+
+impl TaggedComment {
+    /// The content of the logical unit tagged by the comment, giving the name
+    /// of the operator it describes followed by the text of the comment
+    pub fn content(&self) -> String {
+        match (&self.operator, self.text.is_empty()) {
+            (Some(op), true) => op.clone(),
+            (Some(op), false) => format!("{}: {}", op, self.text),
+            (None, _) => self.text.clone(),
+        }
+    }
+}
+
+// Strip the markers of a comment from a `line` that is part of a comment
+fn comment_text(lang: Language, line: &str) -> String {
+    let (open, close) = lang.block_comment();
+    let mut text = line.trim();
+    text = lang
+        .line_comments()
+        .iter()
+        .find_map(|marker| text.strip_prefix(marker))
+        .unwrap_or(text);
+    text = text.strip_prefix(open).unwrap_or(text);
+    text = text.strip_suffix(close).unwrap_or(text);
+    // Remove decorations, like the borders of boxed comments
+    text.trim_matches(|c: char| c == '*' || c == '-' || c == '=' || c.is_whitespace())
+        .to_string()
+}
+
+// The ranges of lines in `lines` which form blocks of comments. Comment lines
+// separated by blank lines are in separate blocks.
+fn comment_blocks(lang: Language, lines: &[&str]) -> Vec<Range<usize>> {
+    let (open, close) = lang.block_comment();
+    let mut blocks = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut in_block_comment = false;
+    for (n, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let is_comment = if in_block_comment {
+            in_block_comment = !trimmed.contains(close);
+            true
+        } else if let Some(rest) = trimmed.strip_prefix(open) {
+            in_block_comment = !rest.contains(close);
+            true
+        } else {
+            lang.line_comments().iter().any(|m| trimmed.starts_with(m))
+        };
+        match (is_comment, start) {
+            (true, None) => start = Some(n),
+            (false, Some(s)) => {
+                blocks.push(s..n);
+                start = None
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        blocks.push(s..lines.len())
+    }
+    blocks
+}
+
+/// `tagged_comments(lang, src)` is all the tags found in comments of the model
+/// `src`, written in the language `lang`
+pub fn tagged_comments(lang: Language, src: &str) -> Vec<TaggedComment> {
+    let lines: Vec<&str> = src.lines().collect();
+    let mut found = Vec::new();
+    for block in comment_blocks(lang, &lines) {
+        let operator = lines[block.end..]
+            .iter()
+            .find(|l| !l.trim().is_empty())
+            .and_then(|l| lang.operator_defined(l));
+        let text = lines[block.clone()]
+            .iter()
+            .map(|l| comment_text(lang, &TAG_ID_RE.replace_all(l, "")))
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let references = logical_unit::references_of_source(
+            block
+                .clone()
+                .map(|n| ((n + 1).try_into().unwrap(), lines[n])),
+        );
+        for n in block {
+            for captures in TAG_ID_RE.captures_iter(lines[n]) {
+                if let Some(tag) = captures.name("tag") {
+                    found.push(TaggedComment {
+                        tag: tag.as_str().to_string(),
+                        line: (n + 1).try_into().unwrap(),
+                        operator: operator.clone(),
+                        text: text.clone(),
+                        references: references.clone(),
+                    })
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_find_tagged_tla_operators() {
+        let src = r#"
+---- MODULE Counter ----
+EXTENDS Naturals
+
+\* |COUNTER.1::INIT.1|
+\* The counter starts at zero
+Init == x = 0
+
+(***************************************************)
+(* |COUNTER.1::NEXT.1|                             *)
+(* The counter is incremented, see [COUNTER.1]     *)
+(***************************************************)
+Next == x' = x + 1
+
+(* |COUNTER.1::ADD.1| *)
+Add(a, b) == a + b
+====
+"#;
+        let actual: Vec<(String, u64, String)> = tagged_comments(Language::Tla, src)
+            .iter()
+            .map(|c| (c.tag.clone(), c.line, c.content()))
+            .collect();
+        let expected = vec![
+            (
+                "COUNTER.1::INIT.1".to_string(),
+                5,
+                "Init: The counter starts at zero".to_string(),
+            ),
+            (
+                "COUNTER.1::NEXT.1".to_string(),
+                10,
+                "Next: The counter is incremented, see [COUNTER.1]".to_string(),
+            ),
+            ("COUNTER.1::ADD.1".to_string(), 15, "Add".to_string()),
+        ];
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn can_find_tagged_quint_operators() {
+        let src = r#"
+module counter {
+  var x: int
+
+  // |COUNTER.1::INIT.1|
+  // The counter starts at zero
+  action init = x' = 0
+
+  /* |COUNTER.1::NEXT.1|
+     The counter is incremented */
+  action step = x' = x + 1
+
+  // |COUNTER.1::ADD.1|
+  pure def add(a, b) = a + b
+}
+"#;
+        let actual: Vec<(String, u64, String)> = tagged_comments(Language::Quint, src)
+            .iter()
+            .map(|c| (c.tag.clone(), c.line, c.content()))
+            .collect();
+        let expected = vec![
+            (
+                "COUNTER.1::INIT.1".to_string(),
+                5,
+                "init: The counter starts at zero".to_string(),
+            ),
+            (
+                "COUNTER.1::NEXT.1".to_string(),
+                9,
+                "step: The counter is incremented".to_string(),
+            ),
+            ("COUNTER.1::ADD.1".to_string(), 13, "add".to_string()),
+        ];
+        assert_eq!(actual, expected)
+    }
+
+    #[test]
+    fn can_find_tagged_alloy_paragraphs() {
+        let src = r#"
+module filesystem
+
+// |FS.1::OBJECT.1|
+// Everything in the file system is an object
+abstract sig Object {}
+
+-- |FS.1::DIR.1| A directory holds objects
+sig Dir extends Object { contents: set Object }
+
+/* |FS.1::ACYCLIC.1|
+   No directory contains itself */
+fact {
+  no d: Dir | d in d.^contents
+}
+"#;
+        let actual: Vec<(String, u64, String)> = tagged_comments(Language::Alloy, src)
+            .iter()
+            .map(|c| (c.tag.clone(), c.line, c.content()))
+            .collect();
+        let expected = vec![
+            (
+                "FS.1::OBJECT.1".to_string(),
+                4,
+                "Object: Everything in the file system is an object".to_string(),
+            ),
+            (
+                "FS.1::DIR.1".to_string(),
+                8,
+                "Dir: A directory holds objects".to_string(),
+            ),
+            (
+                "FS.1::ACYCLIC.1".to_string(),
+                11,
+                "No directory contains itself".to_string(),
+            ),
+        ];
+        assert_eq!(actual, expected)
+    }
+}
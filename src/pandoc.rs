@@ -4,12 +4,9 @@
 
 use {
     anyhow::Result,
-    html2md,
-    itertools::Itertools,
-    scraper,
     std::{
         io,
-        io::{Read, Write},
+        io::Write,
         path::Path,
         process::{Command, ExitStatus, Stdio},
     },
@@ -32,9 +29,6 @@ pub enum Error {
 
     #[error("Could not convert given path to string")]
     Path,
-
-    #[error("Parsing definition list. Encountered {0} while parsing `{1}`")]
-    DefinitionListParsing(String, String),
 }
 
 static PANDOC: &str = "pandoc";
@@ -49,31 +43,44 @@ fn html_from_md_bytes(b: &[u8]) -> Result<String> {
     }
 }
 
-fn parse_string_to_html(s: &str) -> Result<scraper::Html> {
-    html_from_md_bytes(&parse_string(&s)?).map(|s| scraper::Html::parse_fragment(&s))
-}
-
-pub fn parse_string(s: &str) -> Result<Vec<u8>> {
-    let process = Command::new(PANDOC)
-        .args(ARGS)
+// Run pandoc with the given `args`, writing `input` to its stdin, and
+// returning the data it writes to stdout.
+fn run(args: &[&str], input: &str) -> Result<Vec<u8>> {
+    let mut process = Command::new(PANDOC)
+        .args(args)
         .stdout(Stdio::piped())
         .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()
         .map_err(Error::PandocInvocation)?;
 
-    // TODO
+    // The handle to stdin is dropped after writing, closing the pipe, so that
+    // pandoc knows the input is complete.
     process
         .stdin
-        .unwrap()
-        .write_all(s.as_bytes())
+        .take()
+        .ok_or_else(|| Error::PandocData("trying to write to stdin".into()))?
+        .write_all(input.as_bytes())
         .map_err(Error::PandocInvocation)?;
 
-    let mut bytes = Vec::new();
-    process
-        .stdout
-        .ok_or_else(|| Error::PandocData("trying to read from stdout".into()))
-        .and_then(|mut c| c.read_to_end(&mut bytes).map_err(Error::PandocInvocation))?;
-    Ok(bytes)
+    let output = process
+        .wait_with_output()
+        .map_err(Error::PandocInvocation)?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(Error::PandocFailure(
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into(),
+        )
+        .into())
+    }
+}
+
+/// `parse_string(s)` parses the markdown string `s` into html bytes
+pub fn parse_string(s: &str) -> Result<Vec<u8>> {
+    run(ARGS, s)
 }
 
 /// `parse_file(path)` parses the markdown file at `path` into an html string
@@ -97,176 +104,18 @@ pub fn parse_file(path: &Path) -> Result<String> {
     }
 }
 
-/// `html_to_markdown(html, path)` write the `html` to the file at `path`
-/// as markdown.
+/// `html_to_markdown(html)` is the `html` converted into markdown.
 pub fn html_to_markdown(html: &str) -> Result<String> {
-    let process = Command::new(PANDOC)
-        .args(&[
+    let bytes = run(
+        &[
             "--from",
             "html-native_divs-native_spans",
             "--to",
             "markdown",
             "--reference-links",
             "--atx-headers",
-        ])
-        .stdout(Stdio::piped())
-        .stdin(Stdio::piped())
-        .spawn()
-        .map_err(Error::PandocInvocation)?;
-
-    process
-        .stdin
-        .unwrap()
-        .write_all(html.as_bytes())
-        .map_err(Error::PandocInvocation)?;
-
-    let mut bytes = Vec::new();
-    process
-        .stdout
-        .ok_or_else(|| Error::PandocData("trying to read from stdout".into()))
-        .and_then(|mut c| c.read_to_end(&mut bytes).map_err(Error::PandocInvocation))?;
-
+        ],
+        html,
+    )?;
     Ok(String::from_utf8_lossy(&bytes).to_string())
 }
-
-fn parse_file_to_scraper(path: &Path) -> Result<scraper::Html> {
-    parse_file(path).map(|s| scraper::Html::parse_fragment(&s))
-}
-
-fn is_dt(element: &scraper::ElementRef) -> bool {
-    element.value().name() == "dt"
-}
-
-fn is_dd(element: &scraper::ElementRef) -> bool {
-    element.value().name() == "dd"
-}
-
-fn def_parsing_err(msg: &str, element: scraper::ElementRef) -> anyhow::Error {
-    Error::DefinitionListParsing(msg.to_string(), element.html()).into()
-}
-
-// Get the first child and wrap it back up into an ElementRef (if it exists)
-fn get_child_element(el: scraper::ElementRef) -> Option<scraper::ElementRef> {
-    el.first_child().and_then(scraper::ElementRef::wrap)
-}
-
-// Get the inner html after applying the selection
-fn select_inner_html(el: scraper::ElementRef, selector: &scraper::Selector) -> Option<String> {
-    el.select(selector).next().map(|e| e.inner_html())
-}
-
-// Finds a logical unit tag from a def list term, even if it's wrapped in
-// (possibly nested) inline HTML tags.
-fn tag_of_term(term: scraper::ElementRef) -> Option<String> {
-    match get_child_element(term) {
-        None => Some(term.inner_html()),
-        Some(child) => tag_of_term(child),
-    }
-}
-
-fn definitions_from_html(html: scraper::Html) -> Result<Vec<(String, String)>> {
-    // Beware, yucky imperative programming ahead :(
-    let mut defs = Vec::new();
-
-    // These unwraps should only fail if invalid selectors are constructed,
-    // but this is effectively a constant we know at build time and exercise in
-    // our tests
-    let def_lists = scraper::Selector::parse("dl").unwrap();
-    // Either a def term or a definition
-    let def_item = scraper::Selector::parse("dt, dd").unwrap();
-
-    for def_list in html.select(&def_lists) {
-        // Group dt and dd elems together
-        let grouped_elements = def_list
-            .select(&def_item)
-            .into_iter()
-            .group_by(|el| el.value().name());
-        // Arrange
-        let definitions = grouped_elements.into_iter().tuples();
-        for ((term_tag, terms_group), (defs_tag, defs_group)) in definitions {
-            if !(term_tag == "dt" && defs_tag == "dd") {
-                // This generally shouldn't occur, since a definition list without a
-                // leading dt can't appear in markdown. But it could arise from
-                // HTML embedded in a markdown doc.
-                return Err(def_parsing_err("invalid tags on def list groups", def_list));
-            }
-
-            let terms: Vec<scraper::ElementRef> = terms_group.collect();
-
-            // TODO Can we dispense with tese imperative returns?
-            let tag = match terms[..] {
-                [term] => {
-                    if let Some(tag) = tag_of_term(term) {
-                        tag
-                    } else {
-                        return Err(def_parsing_err(
-                            "could not parse html of definition term",
-                            def_list,
-                        ));
-                    }
-                }
-                // This should be impossbile
-                [] => return Err(def_parsing_err("no definition terms", def_list)),
-                _ => {
-                    return Err(def_parsing_err(
-                        "multiple definition terms (not yet supported)",
-                        def_list,
-                    ))
-                }
-            };
-
-            let content = defs_group
-                .map(|el| html2md::parse_html(&el.html()))
-                .join("\n\n");
-
-            defs.push((tag, content));
-        }
-    }
-    Ok(defs)
-}
-
-pub fn definitions_from_file(path: &Path) -> Result<Vec<(String, String)>> {
-    let html = parse_file_to_scraper(path)?;
-    definitions_from_html(html)
-}
-
-pub fn definitions_from_string(s: &str) -> Result<Vec<(String, String)>> {
-    let html = parse_string_to_html(s)?;
-    definitions_from_html(html)
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn find_def_list_with_naked_term() {
-        let input = r#"
-|FOO.1::BAR.1|
-:  Biz baz blam.
-"#;
-        let actual = definitions_from_string(input).unwrap();
-        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
-        assert_eq!(actual, expected)
-    }
-
-    fn find_def_list_with_wrapped_term() {
-        let input = r#"
-<a id="FOO.1::BAR.1">|FOO.1::BAR.1|</a>
-:  Biz baz blam.
-"#;
-        let actual = definitions_from_string(input).unwrap();
-        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
-        assert_eq!(actual, expected)
-    }
-
-    fn find_def_list_with_nestd_wrapped_term() {
-        let input = r#"
-<a id="FOO.1::BAR.1"><em>|FOO.1::BAR.1|</em></a>
-:  Biz baz blam.
-"#;
-        let actual = definitions_from_string(input).unwrap();
-        let expected = vec![("|FOO.1::BAR.1|".into(), "Biz baz blam.".into())];
-        assert_eq!(actual, expected)
-    }
-}
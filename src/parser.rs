@@ -31,18 +31,6 @@ lazy_static! {
 // one place so we can reuse common rules
 peg::parser! {
     pub grammar parser() for str {
-        pub rule letter() -> String =
-            l:$(['a'..='z' | 'A'..='Z' ])
-        { l.to_string() }
-
-        pub rule init_char() -> String =
-            c:$(letter() / ['_'])
-        { c.to_string() }
-
-        pub rule digit() -> String =
-            d:$(['0'..='9'])
-        { d.to_string() }
-
         pub rule logical_unit_definiendum() -> String =
             "|" tag:$(logical_unit_id()) "|"
         { tag.to_string() }
@@ -51,9 +39,12 @@ peg::parser! {
             "[" tag:$(logical_unit_id()) "]"
         { tag.to_string() }
 
+        /// The grammar of ids is given by `kontxt_id`, which is shared with
+        /// the macros, so the id is taken to be the longest run of the
+        /// characters that can occur in one, and then validated
         pub rule logical_unit_id() -> Vec<(String, u32)> =
-            id:(luid_part() ** "::")
-        { ? if id.is_empty() { Err("empty") } else { Ok(id) } }
+            id:$([c if kontxt_id::is_id_char(c)]+)
+        {? kontxt_id::parse(id).ok_or("logical unit id") }
 
         pub rule find_logical_unit_refs() -> Option<Vec<UnitRefSearch>> =
             res:ref_search_result()*
@@ -64,6 +55,16 @@ peg::parser! {
           }
         }
 
+        /// The unit refs in a text, each paired with the byte offset into the
+        /// text at which it starts
+        pub rule logical_unit_refs_at() -> Vec<(usize, String)> =
+            refs:(ref_at() / [_] { None })*
+        { refs.into_iter().flatten().collect() }
+
+        rule ref_at() -> Option<(usize, String)> =
+            p:position!() r:logical_unit_ref()
+        { Some((p, r)) }
+
         rule ref_search_result() -> UnitRefSearch =
             f:(non_ref_text_found() / ref_found())
         { f }
@@ -75,18 +76,6 @@ peg::parser! {
         rule ref_found() -> UnitRefSearch =
             r:logical_unit_ref()
         { UnitRefSearch::Ref(r) }
-
-        rule luid_tag() -> String =
-            t:$(init_char() (init_char() / digit() / "-")+)
-        { t.to_string() }
-
-        rule luid_version() -> u32 =
-            v:$(['1'..='9'] digit()*)
-        { v.parse().unwrap() }
-
-        rule luid_part() -> (String, u32) =
-            t:luid_tag() "." v:luid_version()
-        { (t, v) }
     }
 }
 
@@ -132,6 +121,14 @@ mod test_parser {
         assert_eq!(expected, actual.unwrap())
     }
 
+    #[test]
+    fn logical_unit_refs_at_gives_the_offsets_of_refs() {
+        let actual =
+            parser::logical_unit_refs_at("See [FOO.1], [[BAR.1]] and [NOT.A.REF]").unwrap();
+        let expected = vec![(4, "FOO.1".to_string()), (14, "BAR.1".to_string())];
+        assert_eq!(expected, actual)
+    }
+
     #[test]
     fn find_logical_unit_refs_with_no_refs_is_none() {
         // Ensures we don't error on the empty string
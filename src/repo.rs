@@ -1,17 +1,45 @@
 //! Repos represent any self-contained repository of artifacts.
 //!
 //! A repo can be directory of files or a git repository, or, as a degenerate
-//! case, a single flat file.
+//! case, a single flat file. Only git repositories have upstreams, branches,
+//! and revisions.
 
 use {
+    crate::{
+        config::{self, Config},
+        hosting::{Anchor, Host, Remote, Rev, UrlTemplate},
+        locations,
+        logical_unit::Kind,
+        util,
+    },
     anyhow::Result,
     git2, log,
     serde::{Deserialize, Serialize},
-    std::{fmt, path::PathBuf},
+    std::{
+        fmt, fs, io,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+    thiserror::Error,
 };
 
-const GIT_SSH_PREFIX: &str = "git@github.com:";
-const GITHUB_URL_PREFIX: &str = "https://github.com/";
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid kind override {0}, expected GLOB=KIND")]
+    KindOverrideSyntax(String),
+    #[error("invalid glob {0}: {1}")]
+    Glob(String, glob::PatternError),
+    #[error("{0}")]
+    UnknownKind(String),
+    #[error("the clone of {0} has diverged from its upstream, and cannot be fast-forwarded")]
+    Diverged(String),
+    #[error("no commit found for revision {0} of {1}")]
+    UnknownRev(String, String),
+    #[error("{0} is not a git repository, so it cannot be pinned to a revision")]
+    Unversioned(String),
+    #[error("{0} should be a clone of {1}, but its origin is {2}")]
+    CloneOfOtherUrl(String, String, String),
+}
 
 // NOTE: the level of nesting in this data structure may be unnecssary complicated.
 // The motivation was to abstrasct the underlying representation, in anticipation of
@@ -32,6 +60,10 @@ struct Info {
 enum LocationInfo {
     Local(Info),
     Remote(Info),
+    // A directory that is not a git repository
+    Directory(Info),
+    // A single file
+    File(Info),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -50,12 +82,57 @@ impl Location {
         }
     }
 
-    fn get_info(&self) -> Info {
+    fn new_remote(path: PathBuf, upstream: String) -> Location {
+        Location {
+            inner: LocationInfo::Remote(Info {
+                path,
+                upstream: Some(upstream),
+                branch: None,
+            }),
+        }
+    }
+
+    fn new_plain(path: PathBuf) -> Location {
+        let info = Info {
+            path,
+            upstream: None,
+            branch: None,
+        };
+        Location {
+            inner: if info.path.is_file() {
+                LocationInfo::File(info)
+            } else {
+                LocationInfo::Directory(info)
+            },
+        }
+    }
+
+    fn is_remote(&self) -> bool {
+        matches!(self.inner, LocationInfo::Remote(_))
+    }
+
+    fn info(&self) -> &Info {
         match &self.inner {
-            LocationInfo::Local(info) | LocationInfo::Remote(info) => info.clone(),
+            LocationInfo::Local(info)
+            | LocationInfo::Remote(info)
+            | LocationInfo::Directory(info)
+            | LocationInfo::File(info) => info,
         }
     }
 
+    fn info_mut(&mut self) -> &mut Info {
+        match &mut self.inner {
+            LocationInfo::Local(info)
+            | LocationInfo::Remote(info)
+            | LocationInfo::Directory(info)
+            | LocationInfo::File(info) => info,
+        }
+    }
+
+    fn get_info(&self) -> Info {
+        self.info().clone()
+    }
+
     fn get_upstream_url(&self) -> Option<String> {
         self.get_info().upstream
     }
@@ -65,45 +142,253 @@ impl Location {
     }
 
     fn set_upstream_url(&mut self, url: Option<&str>) {
-        match self.inner {
-            LocationInfo::Local(ref mut info) | LocationInfo::Remote(ref mut info) => {
-                info.upstream = url.map(|s| s.to_string())
-            }
-        };
+        self.info_mut().upstream = url.map(|s| s.to_string())
     }
 
     fn set_default_branch(&mut self, branch: Option<&str>) {
-        match self.inner {
-            LocationInfo::Local(ref mut info) | LocationInfo::Remote(ref mut info) => {
-                info.branch = branch.map(|s| s.to_string())
+        self.info_mut().branch = branch.map(|s| s.to_string())
+    }
+}
+
+/// Sets the kind of the logical units found in the files of a repo that match
+/// a glob, overriding the kind that would otherwise be inferred
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct KindOverride {
+    /// A glob matched against paths relative to the root of the repo
+    pub glob: String,
+    pub kind: Kind,
+}
+
+impl KindOverride {
+    fn matches(&self, path: &Path) -> bool {
+        // The glob is validated when the override is parsed
+        util::glob_matches(&self.glob, path)
+    }
+}
+
+impl FromStr for KindOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (glob, kind) = s
+            .rsplit_once('=')
+            .ok_or_else(|| Error::KindOverrideSyntax(s.to_string()))?;
+        glob::Pattern::new(glob).map_err(|e| Error::Glob(glob.to_string(), e))?;
+        Ok(KindOverride {
+            glob: glob.to_string(),
+            kind: kind.parse().map_err(Error::UnknownKind)?,
+        })
+    }
+}
+
+/// The revision of a repo that a context is pinned to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Pin {
+    /// The branch, tag, or commit the repo is pinned to
+    pub rev: String,
+    /// The id of the commit that the `rev` resolved to when it was last synced
+    pub commit: String,
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.rev, self.commit)
+    }
+}
+
+/// The tree of files of a repo at the commit it is pinned to
+pub struct Tree {
+    repo: git2::Repository,
+    commit: git2::Oid,
+}
+
+impl Tree {
+    /// The paths of all the files in the tree, relative to the root of the
+    /// repo, paired with the ids of their blobs
+    pub fn files(&self) -> Result<Vec<(PathBuf, String)>> {
+        let tree = self.repo.find_commit(self.commit)?.tree()?;
+        let mut files = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if let (Some(git2::ObjectType::Blob), Some(name)) = (entry.kind(), entry.name()) {
+                files.push((Path::new(dir).join(name), entry.id().to_string()))
             }
+            git2::TreeWalkResult::Ok
+        })?;
+        Ok(files)
+    }
+
+    /// Whether there is a file at `path` in the tree
+    pub fn contains(&self, path: &Path) -> Result<bool> {
+        let tree = self.repo.find_commit(self.commit)?.tree()?;
+        Ok(tree.get_path(path).is_ok())
+    }
+
+    /// The contents of the file at `path` in the tree
+    pub fn read(&self, path: &Path) -> Result<String> {
+        let tree = self.repo.find_commit(self.commit)?.tree()?;
+        let blob = self.repo.find_blob(tree.get_path(path)?.id())?;
+        Ok(String::from_utf8_lossy(blob.content()).into())
+    }
+}
+
+/// How the artifacts of a repo are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Git,
+    Directory,
+    File,
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Storage::Git => "git",
+            Storage::Directory => "directory",
+            Storage::File => "file",
         };
+        write!(f, "{}", name)
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Repo {
     location: Location,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    kinds: Vec<KindOverride>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url_template: Option<UrlTemplate>,
+    #[serde(default, skip_serializing_if = "Config::is_default")]
+    config: Config,
 }
 
 impl Repo {
-    // TODO support for default branch and upstream
-    pub fn new_local(path: PathBuf) -> Result<Repo> {
-        let repo = git2::Repository::open(&path)?;
-        let (upstream, branch) = get_repo_remote_and_branch(&repo);
-        let location = Location::new_local(path, upstream, branch);
-        Ok(Repo { location })
+    /// `new_local(path, query_upstream)` is the repo at the local `path`,
+    /// which is either a git repository, a plain directory, or a single file.
+    /// See [`Repo::update`] for how `query_upstream` is used.
+    pub fn new_local(path: PathBuf, query_upstream: bool) -> Result<Repo> {
+        let location = match git2::Repository::open(&path) {
+            Ok(repo) => {
+                let (upstream, branch) = get_repo_remote_and_branch(&repo, None, query_upstream);
+                Location::new_local(path, upstream, branch)
+            }
+            Err(err)
+                if err.code() == git2::ErrorCode::NotFound && path.exists() && is_plain(&path)? =>
+            {
+                Location::new_plain(path)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut repo = Repo {
+            location,
+            kinds: Vec::new(),
+            url_template: None,
+            config: Config::default(),
+        };
+        repo.load_config()?;
+        Ok(repo)
+    }
+
+    /// `new_remote(url, query_upstream)` is the repo at the git `url`, cloned
+    /// into the [`locations::repos_dir`]. If the repo has already been cloned,
+    /// the clone is fast-forwarded to its upstream instead. See
+    /// [`Repo::update`] for how `query_upstream` is used.
+    pub fn new_remote(url: &str, query_upstream: bool) -> Result<Repo> {
+        let path = locations::repos_dir()?.join(clone_dir_of_url(url));
+        if path.exists() {
+            check_origin(&path, url)?;
+        } else {
+            log::info!("cloning {} into {}", url, path.display());
+            // The repo is cloned next to its final location, and only moved
+            // there once the clone succeeds, so a failed clone isn't mistaken
+            // for a complete one
+            let mut partial = path.clone().into_os_string();
+            partial.push(".partial");
+            let partial = PathBuf::from(partial);
+            if partial.exists() {
+                fs::remove_dir_all(&partial)?;
+            }
+            if let Err(err) = git2::Repository::clone(url, &partial) {
+                // The clone may have failed before creating anything
+                let _ = fs::remove_dir_all(&partial);
+                return Err(err.into());
+            }
+            fs::rename(&partial, &path)?;
+        }
+        let mut repo = Repo {
+            location: Location::new_remote(fs::canonicalize(path)?, url.to_string()),
+            kinds: Vec::new(),
+            url_template: None,
+            config: Config::default(),
+        };
+        repo.update(query_upstream)?;
+        Ok(repo)
+    }
+
+    /// Whether the repo is a clone of a remote repo, managed by the tool
+    pub fn is_remote(&self) -> bool {
+        self.location.is_remote()
+    }
+
+    /// `remove_clones(unused, remaining)` deletes the clones of the `unused`
+    /// repos, except for any clone that one of the `remaining` repos still uses
+    pub fn remove_clones(unused: &[Repo], remaining: &[Repo]) -> Result<()> {
+        unused
+            .iter()
+            .filter(|repo| {
+                !remaining
+                    .iter()
+                    .any(|other| other.is_remote() && other.path() == repo.path())
+            })
+            .try_for_each(Repo::remove_clone)
+    }
+
+    /// `remove_clone()` deletes the clone of a remote repo from the
+    /// [`locations::repos_dir`]. Local repos are left alone.
+    pub fn remove_clone(&self) -> Result<()> {
+        if !self.is_remote() {
+            return Ok(());
+        }
+        log::info!(
+            "removing the clone of {} from {}",
+            self,
+            self.path().display()
+        );
+        match fs::remove_dir_all(self.path()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// How the artifacts of the repo are stored
+    pub fn storage(&self) -> Storage {
+        match self.location.inner {
+            LocationInfo::Local(_) | LocationInfo::Remote(_) => Storage::Git,
+            LocationInfo::Directory(_) => Storage::Directory,
+            LocationInfo::File(_) => Storage::File,
+        }
     }
-    // pub fn from_local(path: &Path) -> Result<Repo<'a>, String> {}
 
     pub fn path_as_string(&self) -> String {
         self.location.to_string()
     }
 
+    /// Where the repo is located
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
     /// The local path of a repo
     pub fn path(&self) -> PathBuf {
-        match &self.location.inner {
-            LocationInfo::Local(info) | LocationInfo::Remote(info) => info.path.clone(),
+        self.location.info().path.clone()
+    }
+
+    /// The directory that the paths of the artifacts in the repo are relative
+    /// to. For a repo of a single file, this is the directory of the file.
+    pub fn root(&self) -> PathBuf {
+        let path = self.path();
+        match self.storage() {
+            Storage::File => path.parent().map_or(path.clone(), Path::to_path_buf),
+            Storage::Git | Storage::Directory => path,
         }
     }
 
@@ -116,67 +401,348 @@ impl Repo {
             .unwrap_or_else(|| self.path_as_string())
     }
 
+    /// The URL of the artifact at `file`, relative to the root of the repo.
+    ///
+    /// Artifacts in git repos are linked to at the `rev`, using the repo's URL
+    /// template if it has one, or else the template of the host of its
+    /// upstream. Hosts that aren't recognized are assumed to lay out their
+    /// URLs like GitHub. Any other artifacts are linked to by their `file://`
+    /// URL.
+    pub fn url_of_file(&self, file: &str, rev: Rev, anchor: Anchor) -> String {
+        if self.storage() == Storage::Git {
+            let remote = self
+                .location
+                .get_upstream_url()
+                .and_then(|url| Remote::parse(&url));
+            let template = self
+                .url_template
+                .as_ref()
+                .or(self.config.url_template.as_ref());
+            match (template, remote) {
+                (Some(template), remote) => {
+                    let repo = remote.map_or("".to_string(), |r| r.to_string());
+                    return template.url(&repo, rev, file, anchor);
+                }
+                (None, Some(remote)) => {
+                    let template = remote.host().unwrap_or(Host::GitHub).template();
+                    return template.url(&remote.to_string(), rev, file, anchor);
+                }
+                (None, None) => (),
+            }
+        }
+        let url = format!("file://{}", self.root().join(file).display());
+        match anchor {
+            Anchor::Id(id) => format!("{}#{}", url, id),
+            Anchor::Line(_) => url,
+        }
+    }
+
+    /// Whether the file at `path` (relative to the root of the repo) is parsed
+    /// for units, according to its kind and the configuration of the repo
+    pub fn parses(&self, path: &Path) -> bool {
+        self.config.includes(path) && locations::is_supported_source_path(&self.parsed_as(path))
+    }
+
+    /// The path that the file at `path` is parsed as, according to the
+    /// extensions configured for the repo
+    pub fn parsed_as(&self, path: &Path) -> PathBuf {
+        self.config.parsed_as(path)
+    }
+
+    /// The prefix of the tags of the units defined in the repo, if it owns one
+    pub fn prefix(&self) -> Option<&str> {
+        self.config.prefix.as_deref()
+    }
+
     /// The default branch for the repo
     pub fn get_branch(&self) -> Option<String> {
         self.location.get_branch()
     }
 
-    pub fn update(&mut self) -> Result<()> {
-        let repo = git2::Repository::open(&self.path())?;
-        let (url, branch) = get_repo_remote_and_branch(&repo);
-        self.location.set_upstream_url(url.as_deref());
-        self.location.set_default_branch(branch.as_deref());
+    /// Set the `template` for the URLs of the files in the repo, overriding
+    /// the template of its host and the one configured in the repo
+    pub fn set_url_template(&mut self, template: Option<UrlTemplate>) {
+        self.url_template = template
+    }
+
+    /// Set the `overrides` for the kinds of units found in the repo
+    pub fn set_kind_overrides(&mut self, overrides: Vec<KindOverride>) {
+        self.kinds = overrides
+    }
+
+    /// The kind that units in the file at `path` (relative to the root of the
+    /// repo) are set to have, if any. When several overrides match the `path`,
+    /// the last one takes precedence, and the overrides set when adding the
+    /// repo take precedence over the ones configured in the repo.
+    pub fn kind_override(&self, path: &Path) -> Option<Kind> {
+        self.config
+            .kinds
+            .iter()
+            .chain(self.kinds.iter())
+            .rev()
+            .find(|o| o.matches(path))
+            .map(|o| o.kind.clone())
+    }
+
+    /// `repo.resolve(rev)` pins the `repo` to the commit that the branch, tag,
+    /// or commit `rev` currently resolves to
+    pub fn resolve(&self, rev: &str) -> Result<Pin> {
+        if self.storage() != Storage::Git {
+            return Err(Error::Unversioned(self.path_as_string()).into());
+        }
+        let repo = git2::Repository::open(self.path())?;
+        // Only the checked out branch of a remote repo's clone is tracked
+        // locally, so its other branches are resolved via its `origin`
+        let commit = repo
+            .revparse_single(rev)
+            .or_else(|_| repo.revparse_single(&format!("origin/{}", rev)))
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|_| Error::UnknownRev(rev.to_string(), self.path_as_string()))?;
+        Ok(Pin {
+            rev: rev.to_string(),
+            commit: commit.id().to_string(),
+        })
+    }
+
+    /// The tree of the repo at the commit it is pinned to by the `pin`
+    pub fn tree(&self, pin: &Pin) -> Result<Tree> {
+        Ok(Tree {
+            repo: git2::Repository::open(self.path())?,
+            commit: git2::Oid::from_str(&pin.commit)?,
+        })
+    }
+
+    /// Update the information about the repo from its git repository. The
+    /// clones of remote repos are first fast-forwarded to their upstream.
+    ///
+    /// The default branch is found without going over the network, from the
+    /// `HEAD` of the upstream as last fetched, or else the branch previously
+    /// recorded for the repo, or else the branch checked out. Only if
+    /// `query_upstream` is `true` is the upstream asked for its default
+    /// branch, when the `HEAD` of the upstream isn't known.
+    ///
+    /// The configuration of the repo is loaded again from its files. See
+    /// [`Repo::load_config_at`] for the configuration of a pinned repo.
+    pub fn update(&mut self, query_upstream: bool) -> Result<()> {
+        if self.storage() == Storage::Git {
+            let repo = git2::Repository::open(self.path())?;
+            if self.is_remote() {
+                fast_forward(&repo, &self.path_as_string())?;
+            }
+            let (url, branch) =
+                get_repo_remote_and_branch(&repo, self.get_branch(), query_upstream);
+            self.location.set_upstream_url(url.as_deref());
+            self.location.set_default_branch(branch.as_deref());
+        }
+        self.load_config()
+    }
+
+    // Loads the configuration checked in at the root of the repo. A repo of a
+    // single file has no configuration.
+    fn load_config(&mut self) -> Result<()> {
+        if self.storage() != Storage::File {
+            self.config = Config::load(&self.path())?;
+        }
+        Ok(())
+    }
+
+    /// `load_config_at(pin)` loads the configuration checked in at the root
+    /// of the repo in the commit it is pinned to by the `pin`, rather than the
+    /// one in its working tree
+    pub fn load_config_at(&mut self, pin: &Pin) -> Result<()> {
+        let tree = self.tree(pin)?;
+        let file = Path::new(config::FILE_NAME);
+        self.config = if tree.contains(file)? {
+            let path = self.path().join(file);
+            Config::parse(&path, &tree.read(file)?)?
+        } else {
+            Config::default()
+        };
         Ok(())
     }
 }
 
+/// `is_url(s)` is `true` if `s` is the URL of a git repo, rather than a
+/// local path
+pub fn is_url(s: &str) -> bool {
+    // E.g., `git@github.com:informalsystems/themis-tracer.git`
+    s.contains("://") || s.starts_with("git@")
+}
+
+// Whether the `path`, which can't be opened as a git repository, is a plain
+// directory or file, rather than a broken git repository
+fn is_plain(path: &Path) -> Result<bool> {
+    if fs::metadata(path)?.is_file() {
+        return Ok(true);
+    }
+    match fs::symlink_metadata(path.join(".git")) {
+        Ok(_) => Ok(false),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// The directory, relative to the [`locations::repos_dir`], that the repo at the
+// `url` is cloned into, e.g. `github.com/informalsystems/themis-tracer-<hash>`
+// for `https://github.com/informalsystems/themis-tracer.git`
+//
+// The directory is named after the `url` so it is easy to find, and ends with
+// a hash of the exact `url`, so that no two urls share a clone.
+fn clone_dir_of_url(url: &str) -> PathBuf {
+    let hash = git2::Oid::hash_object(git2::ObjectType::Blob, url.as_bytes())
+        .map(|oid| oid.to_string()[..12].to_string())
+        .unwrap_or_default();
+    let name = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let name = name.split_once('@').map_or(name, |(_, rest)| rest);
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    let mut parts: Vec<&str> = name
+        .split(['/', ':'])
+        .filter(|s| !s.is_empty() && *s != "." && *s != "..")
+        .collect();
+    let last = match parts.pop() {
+        Some(last) => format!("{}-{}", last, hash),
+        None => hash,
+    };
+    parts
+        .into_iter()
+        .map(String::from)
+        .chain(Some(last))
+        .collect()
+}
+
+// Checks that the existing clone at `path` is a clone of the repo at `url`
+fn check_origin(path: &Path, url: &str) -> Result<()> {
+    let git = git2::Repository::open(path)?;
+    let origin = git.find_remote("origin")?;
+    match origin.url() {
+        Some(origin) if origin == url => Ok(()),
+        origin => Err(Error::CloneOfOtherUrl(
+            path.display().to_string(),
+            url.to_string(),
+            origin.unwrap_or_default().to_string(),
+        )
+        .into()),
+    }
+}
+
+// Fetches the `origin` of the clone `repo` of the remote repo at `url`, and
+// fast-forwards the checked out branch to its upstream
+fn fast_forward(repo: &git2::Repository, url: &str) -> Result<()> {
+    repo.find_remote("origin")?
+        .fetch(&[] as &[&str], None, None)?;
+
+    // The branch may be unborn, if the remote was empty when it was cloned
+    let head = repo.find_reference("HEAD")?;
+    let branch = match head.symbolic_target() {
+        Some(branch) => branch.to_string(),
+        // A detached HEAD is left as it is
+        None => return Ok(()),
+    };
+    let upstream = format!(
+        "refs/remotes/origin/{}",
+        branch.strip_prefix("refs/heads/").unwrap_or(&branch)
+    );
+    let target = match repo.refname_to_id(&upstream) {
+        Ok(target) => target,
+        // Nothing has been pushed to the branch yet
+        Err(_) => return Ok(()),
+    };
+    if let Ok(current) = repo.refname_to_id(&branch) {
+        if current == target {
+            return Ok(());
+        } else if !repo.graph_descendant_of(target, current)? {
+            return Err(Error::Diverged(url.to_string()).into());
+        }
+    }
+    repo.reference(&branch, target, true, "kontxt: fast-forward")?;
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    Ok(())
+}
+
 // Assumes the default remote is `upstream` or `origin`, in that order of
 // preference.
-fn get_repo_remote_and_branch(repo: &git2::Repository) -> (Option<String>, Option<String>) {
-    match repo
+// The URL of the upstream of the `repo`, and its default branch (see
+// `Repo::update`)
+fn get_repo_remote_and_branch(
+    repo: &git2::Repository,
+    cached: Option<String>,
+    query_upstream: bool,
+) -> (Option<String>, Option<String>) {
+    let mut remote = match repo
         .find_remote("upstream")
         .or_else(|_| repo.find_remote("origin"))
-        .ok()
     {
-        None => (None, None),
-        Some(mut remote) => {
-            if let Some(remote_url) = remote.url().map(|s| s.to_string()) {
-                match remote.connect(git2::Direction::Fetch) {
-                    Err(err) => {
-                        log::warn!("failed to fetch from remote: {}", err);
-                        // TODO Currently doesn't support fetching from
-                        // authenticated
-                        (Some(remote_url), None)
-                    }
-                    Ok(()) => {
-                        let branch = remote
-                            .default_branch()
-                            .ok()
-                            .map(|buf| String::from_utf8_lossy(&buf.to_vec()).to_string());
-                        (Some(remote_url), branch)
-                    }
-                }
+        Ok(remote) if remote.url().is_some() => remote,
+        _ => return (None, cached.or_else(|| checked_out_branch(repo))),
+    };
+    let url = remote.url().map(|s| s.to_string());
+    let branch = remote
+        .name()
+        .and_then(|name| upstream_head_branch(repo, name))
+        .or_else(|| {
+            if query_upstream {
+                query_default_branch(&mut remote)
             } else {
-                (None, None)
+                None
             }
-        }
+        })
+        .or(cached)
+        .or_else(|| checked_out_branch(repo));
+    (url, branch)
+}
+
+// The branch that the `HEAD` of the `remote` pointed to when it was last
+// fetched, as recorded in `refs/remotes/<remote>/HEAD`
+fn upstream_head_branch(repo: &git2::Repository, remote: &str) -> Option<String> {
+    let head = repo
+        .find_reference(&format!("refs/remotes/{}/HEAD", remote))
+        .ok()?;
+    let prefix = format!("refs/remotes/{}/", remote);
+    head.symbolic_target()?
+        .strip_prefix(&prefix)
+        .map(|s| s.to_string())
+}
+
+// The branch checked out in the `repo`, even if no commits have been made on
+// it yet
+fn checked_out_branch(repo: &git2::Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    head.symbolic_target()?
+        .strip_prefix("refs/heads/")
+        .map(|s| s.to_string())
+}
+
+// Asks the `remote` for its default branch over the network
+fn query_default_branch(remote: &mut git2::Remote) -> Option<String> {
+    if let Err(err) = remote.connect(git2::Direction::Fetch) {
+        // TODO Currently doesn't support fetching from authenticated remotes
+        log::warn!(
+            "failed to query the default branch of the upstream: {}",
+            err
+        );
+        return None;
     }
+    let branch = remote.default_branch().ok()?;
+    let branch = String::from_utf8_lossy(&branch);
+    Some(
+        branch
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&branch)
+            .to_string(),
+    )
 }
 
 // git@github.com:informalsystems/themis-tracer.git -> https://github.com/informalsystems/themis-tracer
 fn normalize_repo_url(url: &str) -> String {
-    if url.starts_with(GIT_SSH_PREFIX) {
-        let url = url.replace(GIT_SSH_PREFIX, GITHUB_URL_PREFIX);
-        url.strip_suffix(".git").unwrap_or(&url).to_string()
-    } else {
-        url.to_string()
-    }
+    Remote::parse(url).map_or(url.to_string(), |remote| remote.to_string())
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.inner {
-            LocationInfo::Local(l) => write!(f, "{}", l.path.as_path().display()),
+            LocationInfo::Local(l) | LocationInfo::Directory(l) | LocationInfo::File(l) => {
+                write!(f, "{}", l.path.as_path().display())
+            }
             // It should be impossible to construct a remote remote without an upstream
             LocationInfo::Remote(r) => write!(f, "{}", r.upstream.as_ref().unwrap()),
         }
@@ -201,4 +767,172 @@ mod test {
             "https://github.com/informalsystems/themis-tracer".to_string()
         )
     }
+
+    #[test]
+    fn can_override_kinds_by_glob() {
+        let mut repo = Repo {
+            location: Location::new_local(PathBuf::from("/repo"), None, None),
+            kinds: Vec::new(),
+            url_template: None,
+            config: Config::default(),
+        };
+        repo.set_kind_overrides(vec![
+            "specs/**/*.rs=model".parse().unwrap(),
+            "specs/tla/*.rs=verification".parse().unwrap(),
+        ]);
+        assert_eq!(
+            repo.kind_override(Path::new("specs/a/b.rs")),
+            Some(Kind::Model)
+        );
+        assert_eq!(
+            repo.kind_override(Path::new("specs/tla/b.rs")),
+            Some(Kind::Verification)
+        );
+        assert_eq!(repo.kind_override(Path::new("src/specs/b.rs")), None);
+        assert!("specs/*.rs".parse::<KindOverride>().is_err());
+        assert!("specs/*.rs=nonsense".parse::<KindOverride>().is_err());
+    }
+
+    #[test]
+    fn can_tell_urls_from_paths() {
+        assert!(is_url(
+            "https://github.com/informalsystems/themis-tracer.git"
+        ));
+        assert!(is_url("git@github.com:informalsystems/themis-tracer.git"));
+        assert!(is_url("file:///repos/remote.git"));
+        assert!(!is_url("repos/repo-a"));
+        assert!(!is_url("/repos/repo-a"));
+    }
+
+    #[test]
+    fn can_name_clone_dirs_after_urls() {
+        let https = clone_dir_of_url("https://github.com/informalsystems/themis-tracer.git");
+        let ssh = clone_dir_of_url("git@github.com:informalsystems/themis-tracer.git");
+        for dir in [&https, &ssh].iter() {
+            assert!(dir.starts_with("github.com/informalsystems"), "{:?}", dir);
+            let name = dir.file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("themis-tracer-"), "{}", name);
+        }
+        assert_ne!(https, ssh);
+        assert_ne!(
+            clone_dir_of_url("file:///repos/../remote.git"),
+            clone_dir_of_url("file:///repos/remote.git")
+        );
+        assert_eq!(
+            clone_dir_of_url("file:///repos/remote.git"),
+            clone_dir_of_url("file:///repos/remote.git")
+        );
+    }
+
+    #[test]
+    fn only_reuses_clones_of_the_same_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        git.remote("origin", "file:///repos/remote.git").unwrap();
+        assert!(check_origin(dir.path(), "file:///repos/remote.git").is_ok());
+        assert!(check_origin(dir.path(), "file:///repos/../remote.git").is_err());
+    }
+
+    #[test]
+    fn can_read_files_at_a_pinned_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("spec.md"), "committed").unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new("spec.md")).unwrap();
+        let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Kontxt", "kontxt@example.com").unwrap();
+        let commit = git
+            .commit(Some("HEAD"), &sig, &sig, "Add a spec", &tree, &[])
+            .unwrap();
+        git.tag_lightweight("v1", &git.find_object(commit, None).unwrap(), false)
+            .unwrap();
+        fs::write(dir.path().join("spec.md"), "changed").unwrap();
+
+        let repo = Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+        let pin = repo.resolve("v1").unwrap();
+        assert_eq!(pin.commit, commit.to_string());
+        assert!(repo.resolve("v2").is_err());
+
+        let tree = repo.tree(&pin).unwrap();
+        let files: Vec<PathBuf> = tree.files().unwrap().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(files, vec![PathBuf::from("spec.md")]);
+        assert_eq!(tree.read(Path::new("spec.md")).unwrap(), "committed");
+    }
+
+    #[test]
+    fn loads_the_configuration_of_pinned_revisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(config::FILE_NAME), "prefix = \"OLD\"").unwrap();
+        let mut index = git.index().unwrap();
+        index.add_path(Path::new(config::FILE_NAME)).unwrap();
+        let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Kontxt", "kontxt@example.com").unwrap();
+        git.commit(Some("HEAD"), &sig, &sig, "Configure", &tree, &[])
+            .unwrap();
+        fs::write(dir.path().join(config::FILE_NAME), "prefix = \"NEW\"").unwrap();
+
+        let mut repo = Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+        assert_eq!(repo.prefix(), Some("NEW"));
+        let pin = repo.resolve("HEAD").unwrap();
+        repo.load_config_at(&pin).unwrap();
+        assert_eq!(repo.prefix(), Some("OLD"));
+    }
+
+    #[test]
+    fn can_add_plain_directories_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("spec.md");
+        fs::write(&file, "# Spec").unwrap();
+
+        let repo = Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+        assert_eq!(repo.storage(), Storage::Directory);
+        assert_eq!(repo.root(), dir.path());
+        assert!(repo.resolve("master").is_err());
+
+        let repo = Repo::new_local(file.clone(), false).unwrap();
+        assert_eq!(repo.storage(), Storage::File);
+        assert_eq!(repo.root(), dir.path());
+        assert_eq!(
+            repo.url_of_file("spec.md", Rev::Branch("master"), Anchor::Id("A.1")),
+            format!("file://{}#A.1", file.display())
+        );
+
+        assert!(Repo::new_local(dir.path().join("missing"), false).is_err());
+
+        // A broken git repository is not mistaken for a plain directory
+        let broken = dir.path().join("broken");
+        git2::Repository::init(&broken).unwrap();
+        fs::remove_dir_all(broken.join(".git").join("objects")).unwrap();
+        assert!(Repo::new_local(broken, false).is_err());
+    }
+
+    #[test]
+    fn can_find_default_branches_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        git.set_head("refs/heads/trunk").unwrap();
+        git.remote("origin", "https://unreachable.invalid/o/r.git")
+            .unwrap();
+
+        let mut repo = Repo::new_local(dir.path().to_path_buf(), false).unwrap();
+        assert_eq!(repo.get_branch(), Some("trunk".to_string()));
+
+        // The branch is cached, rather than following the checked out branch
+        git.set_head("refs/heads/feature").unwrap();
+        repo.update(false).unwrap();
+        assert_eq!(repo.get_branch(), Some("trunk".to_string()));
+
+        // But the upstream's `HEAD`, as last fetched, takes precedence
+        git.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            true,
+            "",
+        )
+        .unwrap();
+        repo.update(false).unwrap();
+        assert_eq!(repo.get_branch(), Some("main".to_string()));
+    }
 }
@@ -0,0 +1,559 @@
+//!
+//! Scanning rust sources for the items tagged with logical units
+//!
+//! Tags are found in the doc comments of items, in `#[implements(...)]`
+//! attributes, and in ordinary comments. Each tag is attached to the item it
+//! documents, or else to the innermost item enclosing it.
+//!
+
+use {
+    crate::{
+        language::{self, SourceLine},
+        logical_unit::{self, Reference},
+        parser::TAG_ID_RE,
+    },
+    std::{
+        collections::HashSet,
+        convert::TryInto,
+        path::{Component, Path},
+    },
+    syn::{
+        punctuated::Punctuated, spanned::Spanned, Attribute, Expr, Fields, ImplItem,
+        Item as SynItem, Lit, LitStr, Meta, Token, TraitItem, Type,
+    },
+};
+
+/// An item defined in a rust source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// The kind of the item, like `fn` or `struct`
+    pub kind: String,
+    /// The path to the item, like `cmd::repo::add`, or the header of an impl
+    /// block, like `impl Display for Foo`
+    pub path: String,
+    /// The (1-based) first and last lines of the item, including its
+    /// attributes
+    pub span: (u64, u64),
+    /// Whether the item is test code
+    pub in_test: bool,
+}
+
+impl Item {
+    /// A description of the item, like `fn cmd::repo::add`
+    pub fn description(&self) -> String {
+        if self.kind == "impl" {
+            self.path.clone()
+        } else {
+            format!("{} {}", self.kind, self.path)
+        }
+    }
+
+    fn contains(&self, line: u64) -> bool {
+        self.span.0 <= line && line <= self.span.1
+    }
+}
+
+/// A tag found in a rust source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub tag: String,
+    /// The (1-based) line on which the tag appears
+    pub line: u64,
+    /// The item that is tagged, if any
+    pub item: Option<Item>,
+    /// The references made in the comments in which the tag appears
+    pub references: Vec<Reference>,
+}
+
+// An item along with the attributes that may tag it
+struct Tagged {
+    item: Item,
+    attrs: Vec<Attribute>,
+}
+
+fn line_of(span: proc_macro2::Span) -> u64 {
+    span.start().line.try_into().unwrap()
+}
+
+// The module path of the source file at `path`, e.g., `["cmd", "repo"]` for
+// `src/cmd/repo.rs`. Only files in a `src` directory are given a path.
+fn module_path_of_file(path: &Path) -> Vec<String> {
+    let components: Vec<String> = path
+        .with_extension("")
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => c.to_str().map(String::from),
+            _ => None,
+        })
+        .collect();
+    let mut module: Vec<String> = match components.iter().rposition(|c| c == "src") {
+        Some(i) => components[i + 1..].to_vec(),
+        None => return vec![],
+    };
+    if module
+        .last()
+        .is_some_and(|m| m == "lib" || m == "main" || m == "mod")
+    {
+        module.pop();
+    }
+    module
+}
+
+// Whether `attr` marks test code, like `#[test]`, `#[tokio::test]`, or
+// `#[cfg(all(test, unix))]`
+fn is_test_attr(attr: &Attribute) -> bool {
+    match &attr.meta {
+        Meta::List(list) if list.path.is_ident("cfg") => list
+            .parse_args::<Meta>()
+            .is_ok_and(|predicate| cfg_requires_test(&predicate)),
+        meta => meta
+            .path()
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "test"),
+    }
+}
+
+// Whether the cfg `predicate` only holds when compiling tests
+fn cfg_requires_test(predicate: &Meta) -> bool {
+    let args = |list: &syn::MetaList| {
+        list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .map(|args| args.into_iter().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    match predicate {
+        Meta::Path(path) => path.is_ident("test"),
+        Meta::List(list) if list.path.is_ident("all") => args(list).iter().any(cfg_requires_test),
+        Meta::List(list) if list.path.is_ident("any") => {
+            let args = args(list);
+            !args.is_empty() && args.iter().all(cfg_requires_test)
+        }
+        _ => false,
+    }
+}
+
+// The text of the doc comment given by `attr`, if it is one
+fn doc_of_attr(attr: &Attribute) -> Option<String> {
+    match &attr.meta {
+        Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(s) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The references made in the doc comments given by the `attrs`
+fn references_of_docs(attrs: &[Attribute]) -> Vec<Reference> {
+    let docs: Vec<(u64, String)> = attrs
+        .iter()
+        .filter_map(|attr| Some((line_of(attr.span()), doc_of_attr(attr)?)))
+        .collect();
+    logical_unit::references_of_source(docs.iter().map(|(line, doc)| (*line, doc.as_str())))
+}
+
+// The tags given to an `#[implements(...)]` attribute, if `attr` is one
+fn implements_of_attr(attr: &Attribute) -> Vec<LitStr> {
+    let is_implements = attr
+        .path()
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "implements");
+    if !is_implements {
+        return vec![];
+    }
+    attr.parse_args_with(Punctuated::<LitStr, Token![,]>::parse_terminated)
+        .map(|tags| tags.into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn attrs_of_fields(fields: &Fields) -> Vec<Attribute> {
+    fields.iter().flat_map(|f| f.attrs.clone()).collect()
+}
+
+// The name of the type implemented by an impl block
+fn name_of_type(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+}
+
+struct Scanner<'a> {
+    source: &'a [SourceLine],
+    found: Vec<Tagged>,
+}
+
+impl<'a> Scanner<'a> {
+    fn add(
+        &mut self,
+        kind: &str,
+        path: String,
+        span: proc_macro2::Span,
+        attrs: Vec<Attribute>,
+        in_test: bool,
+    ) {
+        let item = Item {
+            kind: kind.to_string(),
+            path,
+            span: (line_of(span), span.end().line.try_into().unwrap()),
+            in_test,
+        };
+        self.found.push(Tagged { item, attrs })
+    }
+
+    // The header of the impl block starting on `line`
+    fn impl_header(&self, line: u64) -> Option<String> {
+        let code = &self.source.get(line as usize - 1)?.code;
+        (language::RUST.item_of_line)(code)
+    }
+
+    fn items(&mut self, items: &[SynItem], module: &[String], in_test: bool) {
+        for item in items {
+            self.item(item, module, in_test)
+        }
+    }
+
+    fn item(&mut self, item: &SynItem, module: &[String], in_test: bool) {
+        let path = |name: &dyn ToString| {
+            let mut path = module.to_vec();
+            path.push(name.to_string());
+            path
+        };
+        let (kind, name, attrs) = match item {
+            SynItem::Fn(i) => ("fn", path(&i.sig.ident), i.attrs.clone()),
+            SynItem::Struct(i) => {
+                let mut attrs = i.attrs.clone();
+                attrs.extend(attrs_of_fields(&i.fields));
+                ("struct", path(&i.ident), attrs)
+            }
+            SynItem::Enum(i) => {
+                let mut attrs = i.attrs.clone();
+                for variant in &i.variants {
+                    attrs.extend(variant.attrs.clone());
+                    attrs.extend(attrs_of_fields(&variant.fields));
+                }
+                ("enum", path(&i.ident), attrs)
+            }
+            SynItem::Union(i) => ("union", path(&i.ident), i.attrs.clone()),
+            SynItem::Type(i) => ("type", path(&i.ident), i.attrs.clone()),
+            SynItem::Const(i) => ("const", path(&i.ident), i.attrs.clone()),
+            SynItem::Static(i) => ("static", path(&i.ident), i.attrs.clone()),
+            SynItem::Macro(i) => match &i.ident {
+                Some(ident) => ("macro_rules", path(ident), i.attrs.clone()),
+                None => return,
+            },
+            SynItem::Trait(i) => {
+                let in_test = in_test || i.attrs.iter().any(is_test_attr);
+                let trait_path = path(&i.ident);
+                for trait_item in &i.items {
+                    self.trait_item(trait_item, &trait_path, in_test)
+                }
+                ("trait", trait_path, i.attrs.clone())
+            }
+            SynItem::Mod(i) => {
+                let in_test = in_test || i.attrs.iter().any(is_test_attr);
+                let mod_path = path(&i.ident);
+                if let Some((_, items)) = &i.content {
+                    self.items(items, &mod_path, in_test)
+                }
+                ("mod", mod_path, i.attrs.clone())
+            }
+            SynItem::Impl(i) => {
+                let in_test = in_test || i.attrs.iter().any(is_test_attr);
+                let mut impl_path = module.to_vec();
+                impl_path.extend(name_of_type(&i.self_ty));
+                for impl_item in &i.items {
+                    self.impl_item(impl_item, &impl_path, in_test)
+                }
+                let header = self
+                    .impl_header(line_of(i.impl_token.span))
+                    .unwrap_or_else(|| "impl".into());
+                self.add("impl", header, item.span(), i.attrs.clone(), in_test);
+                return;
+            }
+            _ => return,
+        };
+        let in_test = in_test || attrs.iter().any(is_test_attr);
+        self.add(kind, name.join("::"), item.span(), attrs, in_test)
+    }
+
+    fn impl_item(&mut self, item: &ImplItem, path: &[String], in_test: bool) {
+        let (kind, name, attrs) = match item {
+            ImplItem::Fn(i) => ("fn", i.sig.ident.to_string(), &i.attrs),
+            ImplItem::Const(i) => ("const", i.ident.to_string(), &i.attrs),
+            ImplItem::Type(i) => ("type", i.ident.to_string(), &i.attrs),
+            _ => return,
+        };
+        let in_test = in_test || attrs.iter().any(is_test_attr);
+        let mut path = path.to_vec();
+        path.push(name);
+        self.add(kind, path.join("::"), item.span(), attrs.clone(), in_test)
+    }
+
+    fn trait_item(&mut self, item: &TraitItem, path: &[String], in_test: bool) {
+        let (kind, name, attrs) = match item {
+            TraitItem::Fn(i) => ("fn", i.sig.ident.to_string(), &i.attrs),
+            TraitItem::Const(i) => ("const", i.ident.to_string(), &i.attrs),
+            TraitItem::Type(i) => ("type", i.ident.to_string(), &i.attrs),
+            _ => return,
+        };
+        let in_test = in_test || attrs.iter().any(is_test_attr);
+        let mut path = path.to_vec();
+        path.push(name);
+        self.add(kind, path.join("::"), item.span(), attrs.clone(), in_test)
+    }
+}
+
+// The item tagged by the comment on `line`: the item right after the comment,
+// if only comments come between them, or else the innermost item enclosing
+// the comment.
+fn item_of_comment<'b>(items: &'b [Tagged], source: &[SourceLine], line: u64) -> Option<&'b Item> {
+    let n = line as usize - 1;
+    let next_code = source[n + 1..]
+        .iter()
+        .position(|l| !(l.code.trim().is_empty()))
+        .map(|i| (n + 1 + i + 1) as u64);
+    let following = next_code
+        .filter(|_| source[n].is_comment_only())
+        .and_then(|next| {
+            items
+                .iter()
+                .map(|t| &t.item)
+                .find(|i| i.span.0 > line && i.span.0 <= next)
+        });
+    following.or_else(|| {
+        items
+            .iter()
+            .map(|t| &t.item)
+            .filter(|i| i.contains(line))
+            .min_by_key(|i| i.span.1 - i.span.0)
+    })
+}
+
+/// `tags(path, lines)` is all the tags found in the rust source file at
+/// `path`, with the given `lines`, ordered by the lines they appear on
+pub fn tags(path: &Path, lines: &[String]) -> syn::Result<Vec<Tag>> {
+    let file = syn::parse_file(&lines.join("\n"))?;
+    let source = language::RUST.split_comments(lines);
+    let module = module_path_of_file(path);
+    let mut scanner = Scanner {
+        source: &source,
+        found: Vec::new(),
+    };
+    scanner.items(&file.items, &module, false);
+    if !file.attrs.is_empty() {
+        let path = if module.is_empty() {
+            "crate".into()
+        } else {
+            module.join("::")
+        };
+        let span = (1, lines.len().try_into().unwrap());
+        let item = Item {
+            kind: "mod".into(),
+            path,
+            span,
+            in_test: file.attrs.iter().any(is_test_attr),
+        };
+        scanner.found.push(Tagged {
+            item,
+            attrs: file.attrs.clone(),
+        })
+    }
+    let items = scanner.found;
+
+    let mut tags = Vec::new();
+    for Tagged { item, attrs } in &items {
+        let references = references_of_docs(attrs);
+        for attr in attrs {
+            if let Some(doc) = doc_of_attr(attr) {
+                let start = line_of(attr.span());
+                for (i, doc_line) in doc.lines().enumerate() {
+                    for captures in TAG_ID_RE.captures_iter(doc_line) {
+                        if let Some(tag) = captures.name("tag") {
+                            tags.push(Tag {
+                                tag: tag.as_str().to_string(),
+                                line: start + i as u64,
+                                item: Some(item.clone()),
+                                references: references.clone(),
+                            })
+                        }
+                    }
+                }
+            }
+            for tag in implements_of_attr(attr) {
+                tags.push(Tag {
+                    tag: tag.value(),
+                    line: line_of(tag.span()),
+                    item: Some(item.clone()),
+                    references: references.clone(),
+                })
+            }
+        }
+    }
+
+    // Tags in ordinary comments, which are not seen by the parser
+    let seen: HashSet<(String, u64)> = tags.iter().map(|t| (t.tag.clone(), t.line)).collect();
+    for (n, line) in source.iter().enumerate() {
+        let line_number = (n + 1) as u64;
+        let comment = match &line.comment {
+            Some(comment) => comment,
+            None => continue,
+        };
+        for captures in TAG_ID_RE.captures_iter(comment) {
+            if let Some(tag) = captures.name("tag") {
+                let tag = tag.as_str().to_string();
+                if seen.contains(&(tag.clone(), line_number)) {
+                    continue;
+                }
+                tags.push(Tag {
+                    tag,
+                    line: line_number,
+                    item: item_of_comment(&items, &source, line_number).cloned(),
+                    references: logical_unit::references_of_source(language::comment_block(
+                        &source, n,
+                    )),
+                })
+            }
+        }
+    }
+
+    tags.sort_by(|a, b| (a.line, &a.tag).cmp(&(b.line, &b.tag)));
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags_of(path: &str, src: &str) -> Vec<(String, u64, Option<String>)> {
+        let lines: Vec<String> = src.lines().map(String::from).collect();
+        tags(Path::new(path), &lines)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.tag, t.line, t.item.map(|i| i.description())))
+            .collect()
+    }
+
+    #[test]
+    fn attaches_tags_to_items() {
+        let src = r##"
+/// |FOO.1::IMPL.1| and |FOO.1::IMPL.2|
+pub fn foo() -> &'static str {
+    // |FOO.1::IMPL.3|
+    match "|FOO.1::NOT.1|" {
+        _ => "|FOO.1::NOT.2|",
+    }
+}
+
+mod inner {
+    #[derive(Debug)]
+    #[implements("FOO.1::IMPL.4")]
+    pub struct Bar {
+        /// |FOO.1::IMPL.5|
+        baz: u8,
+    }
+
+    impl Bar {
+        /**
+         * |FOO.1::IMPL.6|
+         */
+        fn new() -> Self {
+            Bar { baz: 0 }
+        }
+    }
+}
+"##;
+        let some = |s: &str| Some(s.to_string());
+        let expected = vec![
+            ("FOO.1::IMPL.1".to_string(), 2, some("fn cmd::foo")),
+            ("FOO.1::IMPL.2".to_string(), 2, some("fn cmd::foo")),
+            ("FOO.1::IMPL.3".to_string(), 4, some("fn cmd::foo")),
+            (
+                "FOO.1::IMPL.4".to_string(),
+                12,
+                some("struct cmd::inner::Bar"),
+            ),
+            (
+                "FOO.1::IMPL.5".to_string(),
+                14,
+                some("struct cmd::inner::Bar"),
+            ),
+            (
+                "FOO.1::IMPL.6".to_string(),
+                20,
+                some("fn cmd::inner::Bar::new"),
+            ),
+        ];
+        assert_eq!(tags_of("src/cmd/mod.rs", src), expected);
+    }
+
+    #[test]
+    fn records_spans_and_test_items() {
+        let src = r#"
+#[cfg(test)]
+mod test {
+    // |FOO.1::TEST.1|
+    #[test]
+    fn it_works() {
+        assert!(true)
+    }
+}
+"#;
+        let lines: Vec<String> = src.lines().map(String::from).collect();
+        let found = tags(Path::new("tests/it.rs"), &lines).unwrap();
+        let item = found[0].item.as_ref().unwrap();
+        assert_eq!(item.description(), "fn test::it_works");
+        assert_eq!(item.span, (5, 8));
+        assert!(item.in_test);
+    }
+
+    #[test]
+    fn recognizes_test_attributes() {
+        let is_test = |attr: &str| {
+            let item: syn::ItemFn = syn::parse_str(&format!("{} fn f() {{}}", attr)).unwrap();
+            is_test_attr(&item.attrs[0])
+        };
+        for attr in [
+            "#[test]",
+            "#[tokio::test]",
+            "#[cfg(test)]",
+            "#[cfg(all(test, unix))]",
+            "#[cfg(all(unix, any(test, all(test, feature = \"x\"))))]",
+        ]
+        .iter()
+        {
+            assert!(is_test(attr), "{} is a test attribute", attr)
+        }
+        for attr in [
+            "#[inline]",
+            "#[cfg(unix)]",
+            "#[cfg(not(test))]",
+            "#[cfg(any(test, unix))]",
+            "#[cfg_attr(test, derive(Debug))]",
+        ]
+        .iter()
+        {
+            assert!(!is_test(attr), "{} is not a test attribute", attr)
+        }
+    }
+
+    #[test]
+    fn finds_module_paths_of_files() {
+        assert_eq!(
+            module_path_of_file(Path::new("src/lib.rs")),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            module_path_of_file(Path::new("crate/src/cmd/repo.rs")),
+            vec!["cmd".to_string(), "repo".to_string()]
+        );
+        assert_eq!(
+            module_path_of_file(Path::new("tests/cli.rs")),
+            Vec::<String>::new()
+        );
+    }
+}
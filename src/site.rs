@@ -1,9 +1,14 @@
 use {
-    crate::graph::UnitGraph,
+    crate::{
+        graph::{self, UnitGraph},
+        logical_unit::{self, Id, LogicalUnit},
+    },
     petgraph::{graph::NodeIndex, Direction},
-    std::fmt,
+    std::{collections::BTreeMap, fmt},
 };
 
+type Occurrences<'a> = BTreeMap<&'a Id, Vec<&'a LogicalUnit>>;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Html {
     Tag(String, Vec<(String, String)>, Vec<Html>),
@@ -17,10 +22,18 @@ fn indent_n(f: &mut fmt::Formatter<'_>, n: u32) -> fmt::Result {
     Ok(())
 }
 
+// Escapes the characters of `text` that are special in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn attrs_to_string(attrs: &[(String, String)]) -> String {
     attrs
         .iter()
-        .map(|(a, v)| format!(r#"{}="{}""#, a, v))
+        .map(|(a, v)| format!(r#"{}="{}""#, a, escape(v)))
         .collect::<Vec<String>>()
         .join(" ")
 }
@@ -62,7 +75,7 @@ impl Html {
             }
             Html::Text(text) => {
                 indent_n(f, indent + 2)?;
-                writeln!(f, "{}", text)
+                writeln!(f, "{}", escape(text))
             }
         }
     }
@@ -76,27 +89,60 @@ impl<'a> fmt::Display for Html {
 
 impl From<&UnitGraph<'_>> for Html {
     fn from(graph: &UnitGraph) -> Html {
-        let unit_tree: Vec<Html> = graph
-            .externals(Direction::Incoming)
-            .flat_map(|i| unit_tree_html(i, graph))
-            .collect();
+        html_of_graph(graph, &BTreeMap::new())
+    }
+}
 
-        tag!(
-            "html",
-            vec![],
-            vec![
-                tag!(
-                    "head",
-                    vec![],
-                    vec![tag!("title", vec![], vec![txt!("Context")])]
-                ),
-                tag!("body", vec![], vec![tag!("dl", vec![], unit_tree)])
-            ]
-        )
+/// The HTML summarizing the `units`, as a tree of units that shows every
+/// occurrence of each tag
+pub fn of_units(units: &[LogicalUnit]) -> Html {
+    html_of_graph(&graph::of_units(units), &logical_unit::occurrences(units))
+}
+
+fn html_of_graph(graph: &UnitGraph, occurrences: &Occurrences) -> Html {
+    let unit_tree: Vec<Html> = graph
+        .externals(Direction::Incoming)
+        .flat_map(|i| unit_tree_html(i, graph, occurrences))
+        .collect();
+
+    tag!(
+        "html",
+        vec![],
+        vec![
+            tag!(
+                "head",
+                vec![],
+                vec![tag!("title", vec![], vec![txt!("Context")])]
+            ),
+            tag!("body", vec![], vec![tag!("dl", vec![], unit_tree)])
+        ]
+    )
+}
+
+// The list of the places where the tag of `unit` occurs, besides the `unit`
+fn occurrences_html(unit: &LogicalUnit, occurrences: &Occurrences) -> Vec<Html> {
+    let items: Vec<Html> = occurrences
+        .get(&unit.id)
+        .into_iter()
+        .flatten()
+        .filter(|u| !std::ptr::eq(**u, unit))
+        .map(|u| {
+            let text = format!("{}: {} {}", u.kind, u.location(), u.content);
+            tag!("li", vec![], vec![txt!(text.trim_end())])
+        })
+        .collect();
+    if items.is_empty() {
+        vec![]
+    } else {
+        vec![tag!("ul", vec![attr!("class", "occurrences")], items)]
     }
 }
 
-fn unit_tree_html(parent_idx: NodeIndex<u32>, graph: &UnitGraph) -> Vec<Html> {
+fn unit_tree_html(
+    parent_idx: NodeIndex<u32>,
+    graph: &UnitGraph,
+    occurrences: &Occurrences,
+) -> Vec<Html> {
     let parent = graph.node_weight(parent_idx).unwrap();
 
     let content = tag!(
@@ -108,7 +154,7 @@ fn unit_tree_html(parent_idx: NodeIndex<u32>, graph: &UnitGraph) -> Vec<Html> {
     let mut implementors: Vec<Html> = {
         let items: Vec<Html> = graph
             .neighbors_directed(parent_idx, Direction::Outgoing)
-            .flat_map(|child| unit_tree_html(child, graph))
+            .flat_map(|child| unit_tree_html(child, graph, occurrences))
             .collect();
         if items.is_empty() {
             vec![]
@@ -125,6 +171,7 @@ fn unit_tree_html(parent_idx: NodeIndex<u32>, graph: &UnitGraph) -> Vec<Html> {
     };
 
     let mut children = vec![content];
+    children.append(&mut occurrences_html(parent, occurrences));
     children.append(&mut implementors);
     vec![
         tag!(
@@ -226,6 +273,59 @@ mod test {
         assert_eq!(expected, actual.to_string())
     }
 
+    #[test]
+    fn shows_every_occurrence_of_a_tag() {
+        let units = vec![
+            LogicalUnit::new(
+                None,
+                None,
+                None,
+                logical_unit::Kind::Implementation,
+                "FOO.1",
+                "fn foo",
+            )
+            .unwrap(),
+            LogicalUnit::new(
+                None,
+                None,
+                None,
+                logical_unit::Kind::Requirement,
+                "FOO.1",
+                "Foo content",
+            )
+            .unwrap(),
+        ];
+        let actual = of_units(&units).to_string();
+        let expected = r#"<html >
+  <head >
+    <title >
+        Context
+    </title>
+  </head>
+  <body >
+    <dl >
+      <dt id="FOO.1">
+        <strong >
+            FOO.1
+        </strong>
+      </dt>
+      <dd >
+        <p class="content">
+            Foo content
+        </p>
+        <ul class="occurrences">
+          <li >
+              Implementation: &lt;unknown&gt; fn foo
+          </li>
+        </ul>
+      </dd>
+    </dl>
+  </body>
+</html>
+"#;
+        assert_eq!(expected, actual)
+    }
+
     #[test]
     fn can_write_html() {
         let html = tag!(
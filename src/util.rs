@@ -1,5 +1,7 @@
 //! Helpers
 
+use std::path::Path;
+
 /// `some_if(cond, f)` is `Some(f())` if `cond` is `true`, or else `None`
 pub(crate) fn some_if<F, T>(cond: bool, f: F) -> Option<T>
 where
@@ -11,3 +13,13 @@ where
         Some(f())
     }
 }
+
+/// `glob_matches(glob, path)` is `true` if the `glob` matches the `path`, with
+/// `*` never matching a `/`. Invalid globs match nothing.
+pub(crate) fn glob_matches(glob: &str, path: &Path) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    glob::Pattern::new(glob).is_ok_and(|p| p.matches_path_with(path, options))
+}
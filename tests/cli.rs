@@ -0,0 +1,104 @@
+//! Tests of the exit status of the `kontxt` executable, for the commands used
+//! to gate changes in CI
+
+use {
+    assert_cmd::Command,
+    std::{fs, path::Path},
+    tempfile::TempDir,
+};
+
+// A `kontxt` command, using the `home` directory for its db
+fn kontxt(home: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("kontxt").unwrap();
+    cmd.env("TRACER_HOME", home).env("RUST_LOG", "error");
+    cmd
+}
+
+// A home directory, initialized so that commands only print their own output
+fn initialized_home() -> TempDir {
+    let home = tempfile::tempdir().unwrap();
+    kontxt(home.path()).arg("init").assert().success();
+    home
+}
+
+// A directory holding a single spec with the given `content`
+fn repo_with_spec(content: &str) -> TempDir {
+    let repo = tempfile::tempdir().unwrap();
+    fs::write(repo.path().join("spec.md"), content).unwrap();
+    repo
+}
+
+#[test]
+fn check_fails_on_problems_of_the_configured_severity() {
+    let home = initialized_home();
+    // The requirement has no implementation, which is only a warning
+    let repo = repo_with_spec("|FOO.1|\n: Foo.\n");
+
+    kontxt(home.path())
+        .arg("check")
+        .arg(repo.path())
+        .assert()
+        .success();
+    kontxt(home.path())
+        .args(["check", "--fail-on", "warning"])
+        .arg(repo.path())
+        .assert()
+        .failure()
+        .code(1);
+    kontxt(home.path())
+        .args(["check", "--fail-on", "warning"])
+        .args(["--level", "unimplemented-requirement=allow"])
+        .arg(repo.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn dangling_references_fail_the_check() {
+    let home = initialized_home();
+    let repo = repo_with_spec("|FOO.1|\n: Foo.\n\n|FOO.1::BAR.1|\n: Bar,\n  see [BAZ.1].\n");
+
+    let output = kontxt(home.path())
+        .args(["check", "--format", "json"])
+        .arg(repo.path())
+        .assert()
+        .failure()
+        .code(1)
+        .get_output()
+        .stdout
+        .clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(summary["errors"], 1);
+    assert_eq!(summary["failed"], true);
+    assert_eq!(summary["diagnostics"][0]["check"], "dangling-reference");
+    let location = summary["diagnostics"][0]["location"].as_str().unwrap();
+    assert!(location.ends_with("spec.md:6"), "{}", location);
+}
+
+#[test]
+fn dangling_references_fail_sync_when_denied() {
+    let home = initialized_home();
+    let repo = repo_with_spec("|FOO.1|\n: Foo, see [BAZ.1].\n");
+
+    kontxt(home.path()).args(["new", "foo"]).assert().success();
+    kontxt(home.path())
+        .args(["repo", "add"])
+        .arg(repo.path())
+        .assert()
+        .success();
+    let output = kontxt(home.path())
+        .args(["sync", "--deny-dangling"])
+        .assert()
+        .failure()
+        .code(1)
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(
+        stderr.contains("spec.md:2: FOO.1 refers to missing unit BAZ.1"),
+        "{}",
+        stderr
+    );
+    kontxt(home.path()).arg("check").assert().failure().code(1);
+}